@@ -0,0 +1,139 @@
+//! Emission backends, which receive the compiler's output piece by piece.
+
+use crate::types;
+use std::io;
+use std::io::Write;
+
+/// Borrows each of a list of strings, as backends take them.
+pub(crate) fn strs(strings: &[String]) -> Vec<&str> {
+	strings.iter().map(String::as_str).collect()
+}
+
+/// Receives each piece of a compiled script in order.
+pub trait EmitBackend {
+	/// A bytecode instruction: its opcode constant and the operands which follow it, one byte each.
+	fn instruction(&mut self, opcode: &str, operands: &[&str]) -> io::Result<()>;
+	/// An invocation of an assembly macro defined with `macro`, whose size only the assembler knows.
+	fn macro_invocation(&mut self, name: &str, args: &[&str]) -> io::Result<()>;
+	/// A label definition, such as `.__end0` or `MyScript::`.
+	fn label(&mut self, name: &str) -> io::Result<()>;
+	/// Raw data, `size` bytes per value. A quoted string is one byte per character.
+	fn data(&mut self, size: u8, values: &[&str]) -> io::Result<()>;
+	/// Anything else: sections, definitions, comments and inline assembly.
+	fn directive(&mut self, line: &str) -> io::Result<()>;
+}
+
+/// One piece of a compiled script, as it is handed to a backend.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Op {
+	Instruction { opcode: String, operands: Vec<String> },
+	Macro { name: String, args: Vec<String> },
+	Label(String),
+	Data { size: u8, values: Vec<String> },
+	Directive(String),
+}
+
+impl Op {
+	/// Hands this piece to `backend`.
+	pub fn emit<B: EmitBackend + ?Sized>(&self, backend: &mut B) -> io::Result<()> {
+		match self {
+			Op::Instruction { opcode, operands } => backend.instruction(opcode, &strs(operands)),
+			Op::Macro { name, args } => backend.macro_invocation(name, &strs(args)),
+			Op::Label(name) => backend.label(name),
+			Op::Data { size, values } => backend.data(*size, &strs(values)),
+			Op::Directive(line) => backend.directive(line),
+		}
+	}
+
+	/// How many bytes this piece assembles to, or `None` for a macro, which may be any size.
+	pub fn size(&self) -> Option<usize> {
+		match self {
+			Op::Instruction { operands, .. } => Some(1 + operands.len()),
+			Op::Macro { .. } => None,
+			Op::Label(..) | Op::Directive(..) => Some(0),
+			Op::Data { size, values } => Some(values.iter()
+				.map(|value| match value.strip_prefix('"').and_then(|value| value.strip_suffix('"')) {
+					// The compiler writes strings with the escapes RGBDS knows, which stand for a byte each.
+					Some(text) => types::string_length(text).unwrap_or(text.len()),
+					None => *size as usize,
+				})
+				.sum()),
+		}
+	}
+}
+
+/// Records every piece, so that it can be measured or moved before being emitted.
+impl EmitBackend for Vec<Op> {
+	fn instruction(&mut self, opcode: &str, operands: &[&str]) -> io::Result<()> {
+		self.push(Op::Instruction {
+			opcode: String::from(opcode),
+			operands: operands.iter().map(|i| String::from(*i)).collect(),
+		});
+		Ok(())
+	}
+
+	fn macro_invocation(&mut self, name: &str, args: &[&str]) -> io::Result<()> {
+		self.push(Op::Macro {
+			name: String::from(name),
+			args: args.iter().map(|i| String::from(*i)).collect(),
+		});
+		Ok(())
+	}
+
+	fn label(&mut self, name: &str) -> io::Result<()> {
+		self.push(Op::Label(String::from(name)));
+		Ok(())
+	}
+
+	fn data(&mut self, size: u8, values: &[&str]) -> io::Result<()> {
+		self.push(Op::Data { size, values: values.iter().map(|i| String::from(*i)).collect() });
+		Ok(())
+	}
+
+	fn directive(&mut self, line: &str) -> io::Result<()> {
+		self.push(Op::Directive(String::from(line)));
+		Ok(())
+	}
+}
+
+/// Writes RGBDS assembly, as the compiler does by default.
+pub struct RgbdsBackend<W: Write> {
+	pub output: W,
+}
+
+impl<W: Write> RgbdsBackend<W> {
+	pub fn new(output: W) -> Self {
+		Self { output }
+	}
+}
+
+impl<W: Write> EmitBackend for RgbdsBackend<W> {
+	fn instruction(&mut self, opcode: &str, operands: &[&str]) -> io::Result<()> {
+		write!(self.output, "\tdb {opcode}")?;
+		for i in operands {
+			write!(self.output, ", {i}")?;
+		}
+		writeln!(self.output)
+	}
+
+	fn macro_invocation(&mut self, name: &str, args: &[&str]) -> io::Result<()> {
+		write!(self.output, "\t{name}")?;
+		for i in args {
+			write!(self.output, " {i},")?;
+		}
+		writeln!(self.output)
+	}
+
+	fn label(&mut self, name: &str) -> io::Result<()> {
+		writeln!(self.output, "{name}")
+	}
+
+	fn data(&mut self, size: u8, values: &[&str]) -> io::Result<()> {
+		let directive = if size == 2 { "dw" } else { "db" };
+		writeln!(self.output, "\t{directive} {}", values.join(", "))
+	}
+
+	fn directive(&mut self, line: &str) -> io::Result<()> {
+		writeln!(self.output, "{line}")
+	}
+}
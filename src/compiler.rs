@@ -1,19 +1,42 @@
+use crate::backend::{strs, EmitBackend, Op, RgbdsBackend};
 use crate::types;
 use crate::types::Rpn;
 use crate::types::Statement;
 use crate::types::StatementType;
 
 use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::HashSet;
 use std::convert::From;
 use std::fmt;
+use std::fs::canonicalize;
 use std::fs::read_to_string;
+use std::io;
 use std::io::Write;
-use std::process::exit;
+use std::path::Path;
+use std::path::PathBuf;
 
 pub struct CompilerError {
 	pub msg: String,
 	pub start: Option<usize>,
 	pub end: Option<usize>,
+	/// Where the thing being redefined was first defined.
+	pub previous: Option<std::ops::Range<usize>>,
+	/// The included file the spans belong to, or None if they are in the file being compiled.
+	pub file: Option<String>,
+}
+
+impl CompilerError {
+	/// An error covering `start..end` of the source.
+	pub fn spanned(start: usize, end: usize, msg: String) -> Self {
+		CompilerError {
+			msg,
+			start: Some(start),
+			end: Some(end),
+			previous: None,
+			file: None,
+		}
+	}
 }
 
 impl From<&str> for CompilerError {
@@ -22,6 +45,8 @@ impl From<&str> for CompilerError {
 			msg: String::from(msg),
 			start: None,
 			end: None,
+			previous: None,
+			file: None,
 		}
 	}
 }
@@ -32,6 +57,8 @@ impl From<String> for CompilerError {
 			msg,
 			start: None,
 			end: None,
+			previous: None,
+			file: None,
 		}
 	}
 }
@@ -42,6 +69,31 @@ impl From<std::io::Error> for CompilerError {
 			msg: msg.to_string(),
 			start: None,
 			end: None,
+			previous: None,
+			file: None,
+		}
+	}
+}
+
+impl From<crate::ParseError<'_>> for CompilerError {
+	fn from(err: crate::ParseError) -> Self {
+		let (start, end) = match &err {
+			lalrpop_util::ParseError::InvalidToken { location } => (Some(*location), Some(*location)),
+			lalrpop_util::ParseError::UnrecognizedEof { location, .. } => (Some(*location), Some(*location)),
+			lalrpop_util::ParseError::UnrecognizedToken { token: (l, _, r), .. } => (Some(*l), Some(*r)),
+			lalrpop_util::ParseError::ExtraToken { token: (l, _, r) } => (Some(*l), Some(*r)),
+			lalrpop_util::ParseError::User { .. } => (None, None),
+		};
+		let msg = match crate::reserved_word(&err) {
+			Some(word) => format!("{word} is a reserved word, and cannot be used as a name"),
+			None => err.to_string(),
+		};
+		CompilerError {
+			msg,
+			start,
+			end,
+			previous: None,
+			file: None,
 		}
 	}
 }
@@ -61,6 +113,13 @@ impl fmt::Display for CompilerError {
 }
 
 impl CompilerError {
+	/// Attributes a diagnostic to the included file at `path`,
+	/// unless it already belongs to a file that one includes in turn.
+	pub fn in_file(mut self, path: &str) -> Self {
+		self.file.get_or_insert_with(|| String::from(path));
+		self
+	}
+
 	pub fn get_range(&self) -> Option<std::ops::Range<usize>> {
 		if let Some(start) = self.start {
 			if let Some(end) = self.end {
@@ -74,16 +133,149 @@ impl CompilerError {
 	}
 }
 
+/// How to check the targets of macro definitions, which are assembler macros that evscript cannot see.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MacroCheck {
+	/// Precede each use of a macro with an `assert` that its target is defined.
+	Assert,
+	/// Warn once, listing the target of every macro that was used.
+	Warn,
+}
+
+#[derive(Clone)]
 pub struct CompilerOptions {
-	pub report_usage: bool,
+	/// List the definitions of each environment which no function ended up using.
+	pub report_unused: bool,
+	/// Omit the terminating `db 0` of every function, as if each were `@falls_through`.
+	pub no_terminator: bool,
+	/// Treat type mismatches that would otherwise warn (such as incompatible pointers) as errors.
+	pub strict_types: bool,
+	/// Warn when a `yield` occurs while more than this many variables are live.
+	pub yield_depth_limit: Option<usize>,
+	/// Use far calls between functions even when they are not known to be in different banks.
+	pub far_calls: bool,
+	/// The environment of functions which do not name one.
+	pub assumed_environment: Option<String>,
+	/// Fail if a function's bytecode, including its strings, is larger than this many bytes.
+	/// Functions which invoke macros are also checked by the assembler, which knows their size.
+	pub max_function_size: Option<usize>,
+	/// Write a dispatch table for this environment's bytecodes.
+	pub dispatch_environment: Option<String>,
+	/// Environments which must have a given `version`.
+	pub required_versions: Vec<(String, i64)>,
+	/// The `feature` blocks of environments which are enabled.
+	pub features: Vec<String>,
+	/// Define `{function}@{variable}` to the slot of each variable as it is declared.
+	pub variable_equates: bool,
+	/// Fail if includes are nested more than this many levels deep.
+	pub include_depth_limit: usize,
+	/// Directories searched for an included file which is neither next to the file including it nor in the current directory.
+	pub include_paths: Vec<String>,
+	/// Store 16-bit values and operands with their high byte first.
+	pub big_endian: bool,
+	/// Encode the target of each jump as its offset from the jump, rather than as an address.
+	pub relative_jumps: bool,
+	/// The definition which ends each function, instead of the one with bytecode 0.
+	pub terminator: Option<String>,
+	/// The type of integer constants in environments which don't choose one with `default_int`.
+	pub default_int: Option<String>,
+	/// Leave out the environments' bytecode equates, as they are in a header written separately.
+	pub no_header: bool,
+	/// Describe the layout of each environment's pool, and how much of it each function uses, in comments.
+	pub pool_map: bool,
+	/// Leave out branches and loops which a constant condition makes unreachable.
+	pub optimize: bool,
+	/// Precede the bytecode of each statement with a comment naming its file and line.
+	pub line_directives: bool,
+	/// Check that the targets of the macros which are used exist, if at all.
+	pub check_macros: Option<MacroCheck>,
+	/// Constants defined on the command line, which may be used wherever a constant expression is expected.
+	pub defines: HashMap<String, i64>,
 }
 
 impl CompilerOptions {
 	pub fn new() -> CompilerOptions {
 		CompilerOptions {
-			report_usage: false,
+			report_unused: false,
+			no_terminator: false,
+			strict_types: false,
+			yield_depth_limit: None,
+			far_calls: false,
+			assumed_environment: None,
+			max_function_size: None,
+			dispatch_environment: None,
+			required_versions: Vec::new(),
+			features: Vec::new(),
+			variable_equates: false,
+			include_depth_limit: 64,
+			include_paths: Vec::new(),
+			big_endian: false,
+			relative_jumps: false,
+			terminator: None,
+			default_int: None,
+			no_header: false,
+			pool_map: false,
+			optimize: false,
+			line_directives: false,
+			check_macros: None,
+			defines: HashMap::new(),
+		}
+	}
+
+	/// The options preset by a `--target` profile.
+	pub fn target(name: &str) -> Result<CompilerOptions, String> {
+		let mut options = CompilerOptions::new();
+		match name {
+			// The defaults are those of the Game Boy's VM: little-endian, with u8 integers,
+			// absolute jumps, and functions ending with bytecode 0.
+			"gb-default" => {}
+			"custom-be" => {
+				options.big_endian = true;
+				options.relative_jumps = true;
+				options.terminator = Some(String::from("ret"));
+				options.default_int = Some(String::from("u16"));
+			}
+			_ => return Err(format!("Unknown target {name}; expected one of: gb-default custom-be")),
 		}
+		Ok(options)
+	}
+}
+
+/// Statistics about the code produced by a compilation.
+#[derive(Debug, Default)]
+pub struct CompilerSummary {
+	pub functions: usize,
+	/// The size of every function, including its strings, but not the macros they invoke.
+	pub function_bytes: usize,
+	/// How many macro invocations there are, which only the assembler knows the size of.
+	pub macro_invocations: usize,
+	pub data_sections: usize,
+	pub data_bytes: usize,
+	/// The most pool bytes each function had in use at once, in the order they were compiled.
+	pub peak_usage: Vec<(String, usize)>,
+	/// The path of every file which was included, in the order they were first included.
+	pub included_files: Vec<String>,
+	/// With `report_unused`, the environment and name of each definition which no function used.
+	pub unused_definitions: Vec<(String, String)>,
+}
+
+/// The operands of a `db` directive which emits a string, whose escape sequences were decoded.
+/// Characters are quoted, escaping those RGBDS would not read as themselves, so that they still go through the charmap.
+/// The bytes of `\0` and `\xNN` are operands of their own, since RGBDS has no escape sequence for them.
+fn string_operands(pieces: &[types::StringPiece]) -> Vec<String> {
+	let mut operands = pieces.iter()
+		.map(|piece| match piece {
+			types::StringPiece::Text(text) => {
+				let escaped = text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n").replace('\t', "\\t");
+				format!("\"{escaped}\"")
+			}
+			types::StringPiece::Byte(byte) => format!("${byte:02X}"),
+		})
+		.collect::<Vec<String>>();
+	if operands.is_empty() {
+		operands.push(String::from("\"\""));
 	}
+	operands
 }
 
 #[derive(Debug)]
@@ -91,12 +283,87 @@ struct Environment {
 	name: String,
 	definitions: HashMap<String, types::Definition>,
 	pool: u16,
+	/// Every environment brought in through `use`, including indirect ones.
+	uses: Vec<String>,
+	/// The environment each definition was originally made in.
+	origins: HashMap<String, String>,
+	/// The bytecode version set by a `version` statement, if any.
+	version: Option<i64>,
+	/// The value of `null` set by a `null` statement, if any.
+	null: Option<u16>,
+	/// The type of integer constants set by a `default_int` statement, if any.
+	default_int: Option<Primative>,
+	reservations: Vec<Reservation>,
+	/// Definitions left out because their feature is disabled, along with that feature.
+	disabled: HashMap<String, String>,
+	/// Every definition which bytecode was emitted for, through the environment or an alias.
+	used: RefCell<HashSet<String>>,
+	/// The file the environment was defined in, and where.
+	file: String,
+	start: usize,
+	end: usize,
+}
+
+/// A region of the pool set aside by a `reserve` statement, which is never allocated.
+#[derive(Debug, Clone)]
+struct Reservation {
+	/// Named reservations may be used like any other variable.
+	name: Option<String>,
+	offset: u8,
+	t: Type,
 }
 
 impl Environment {
+	/// Maps each bytecode back to the name of its definition, or `None` for unused bytecodes.
+	/// Aliases and macros have no bytecode of their own, so only `def`s are listed.
+	fn bytecode_names(&self) -> Vec<Option<&str>> {
+		let mut names = Vec::<Option<&str>>::new();
+
+		let mut definitions = self.definitions.keys().collect::<Vec<&String>>();
+		definitions.sort();
+
+		for name in definitions {
+			if let types::Definition::Def(def) = &self.definitions[name] {
+				let index = def.bytecode as usize;
+				if names.len() <= index {
+					names.resize(index + 1, None);
+				}
+				// Should several names share a bytecode, the first one alphabetically is used.
+				names[index].get_or_insert(name);
+			}
+		}
+
+		names
+	}
+
+	fn reserve(&mut self, reservation: Reservation) -> Result<(), String> {
+		let start = reservation.offset as usize;
+		let end = start + reservation.t.size() as usize;
+
+		if end > 256 {
+			return Err(format!("Reserved region {start}-{} does not fit in the pool", end - 1));
+		}
+
+		for i in &self.reservations {
+			let other_start = i.offset as usize;
+			let other_end = other_start + i.t.size() as usize;
+			if start < other_end && other_start < end {
+				return Err(format!(
+					"Reserved region {start}-{} overlaps with {other_start}-{}",
+					end - 1,
+					other_end - 1,
+				));
+			}
+		}
+
+		self.reservations.push(reservation);
+		Ok(())
+	}
+
 	fn expand(&self, name: &str) -> Result<String, String> {
 		match self.lookup(name)? {
 			types::Definition::Def(..) => {
+				self.used.borrow_mut().insert(String::from(name));
 				Ok(format!("{}@{}", self.name, name))
 			}
 			types::Definition::Alias(alias) => {
@@ -109,13 +376,122 @@ impl Environment {
 	fn lookup(&self, name: &str) -> Result<&types::Definition, String> {
 		match self.definitions.get(name) {
 			Some(def) => Ok(def),
-			None => return Err(format!("Definition of {name} not found")),
+			None => match self.disabled.get(name) {
+				Some(feature) => Err(format!("{name} is only available with the {feature} feature enabled")),
+				None => Err(format!("Definition of {name} not found")),
+			},
 		}
 	}
+
+	/// Whether a function written for `other` may run under this environment.
+	fn is_compatible(&self, other: &str) -> bool {
+		self.name == other || self.uses.iter().any(|i| i == other)
+	}
 }
 
 type EnvironmentTable = HashMap<String, Environment>;
 
+#[derive(Debug)]
+struct FunctionInfo {
+	environment: String,
+	/// The ROM bank requested by `@bank(N)`, if any.
+	bank: Option<u16>,
+	/// The file the function was defined in, and where.
+	file: String,
+	start: usize,
+	end: usize,
+}
+
+impl FunctionInfo {
+	fn new(func: &types::Function, file: &str, options: &CompilerOptions) -> Result<FunctionInfo, CompilerError> {
+		let mut bank = None;
+
+		for i in &func.attributes {
+			if i.name == "bank" {
+				let attribute_error = |msg: String| CompilerError::spanned(i.start, i.end, msg);
+
+				if i.args.len() != 1 {
+					return Err(attribute_error(String::from("@bank expects a single bank number")));
+				}
+				let value = i.args[0].eval_const_defined(&options.defines).map_err(attribute_error)?;
+				bank = Some(u16::try_from(value)
+					.ok()
+					.filter(|bank| *bank != 0)
+					.ok_or(attribute_error(format!("Invalid ROMX bank {value}")))?);
+			}
+		}
+
+		let environment = func.environment.as_ref()
+			.or(options.assumed_environment.as_ref())
+			.ok_or(CompilerError::spanned(func.start, func.end, String::from("Function has no environment, and no environment is assumed")))?;
+
+		Ok(FunctionInfo {
+			environment: environment.clone(),
+			bank,
+			file: String::from(file),
+			start: func.start,
+			end: func.end,
+		})
+	}
+}
+
+type FunctionTable = HashMap<String, FunctionInfo>;
+
+/// Finds the line of each offset into a file.
+#[derive(Clone)]
+struct SourceLines {
+	file: String,
+	/// The offset at which each line begins.
+	starts: Vec<usize>,
+}
+
+impl SourceLines {
+	fn new(file: &str, input: &str) -> SourceLines {
+		let starts = std::iter::once(0)
+			.chain(input.match_indices('\n').map(|(i, _)| i + 1))
+			.collect();
+		SourceLines { file: String::from(file), starts }
+	}
+
+	/// The line, counting from 1, containing `offset`.
+	fn line(&self, offset: usize) -> usize {
+		self.starts.partition_point(|start| *start <= offset)
+	}
+}
+
+/// State belonging to the function currently being compiled.
+struct FunctionState {
+	name: String,
+	/// The operands of each of the function's strings.
+	strings: Vec<Vec<String>>,
+	/// The `data` tables of the function, placed after it like its strings.
+	data: Vec<Op>,
+	/// Used to give each comparison chain and conditional expression its own labels.
+	expression_label: u32,
+	/// Warnings about the function, reported once it has been compiled.
+	warnings: Vec<CompilerError>,
+	/// The lines of the function's file, if statements are annotated with them.
+	source: Option<SourceLines>,
+	/// The type of the function's integer constants.
+	int_type: Primative,
+}
+
+impl FunctionState {
+	/// Returns the label of a string placed after the function, decoding its escape sequences.
+	/// Identical strings share their bytes.
+	fn string_label(&mut self, string: &str) -> Result<String, String> {
+		let operands = string_operands(&types::unescape(string)?);
+		let index = match self.strings.iter().position(|i| *i == operands) {
+			Some(index) => index,
+			None => {
+				self.strings.push(operands);
+				self.strings.len() - 1
+			}
+		};
+		Ok(format!(".__string{index}"))
+	}
+}
+
 #[derive(Debug, Clone, PartialEq)]
 enum Type {
 	Primative(Primative),
@@ -139,12 +515,40 @@ impl Type {
 			}
 		}
 	}
+
+	/// Wraps a type in `depth` levels of pointers.
+	fn pointer_to(t: Type, depth: usize) -> Type {
+		(0..depth).fold(t, |t, _| Type::Pointer(Box::new(t)))
+	}
+
+	/// Lists the offset and type of every primative in this type, in order, looking into nested structs.
+	fn primatives(&self) -> Vec<(u8, Primative)> {
+		match self {
+			Type::Primative(t) => vec![(0, *t)],
+			Type::Pointer(_) => vec![(0, Primative::pointer())],
+			Type::Struct(t) => {
+				let mut result = Vec::new();
+				let mut offset = 0;
+
+				for (_, i) in t {
+					for (member_offset, member) in i.primatives() {
+						result.push((offset + member_offset, member));
+					}
+					offset += i.size();
+				}
+
+				result
+			}
+		}
+	}
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 struct Primative {
 	signed: bool,
 	size: u8,
+	/// An 8.8 fixed-point number. It uses the same bytecode as the integer of its size.
+	fixed: bool,
 }
 
 impl Primative {
@@ -152,14 +556,16 @@ impl Primative {
 		Primative {
 			signed: l.signed || r.signed,
 			size: if l.size >= r.size { l.size } else { r.size },
+			fixed: l.fixed || r.fixed,
 		}
 	}
 	
-	// TODO: make the default_integer type configurable
+	/// The type of integer constants in environments which don't choose one with `default_int`.
 	fn default_integer() -> Primative {
 		Primative {
 			signed: false,
 			size: 1,
+			fixed: false,
 		}
 	}
 	
@@ -167,7 +573,16 @@ impl Primative {
 	fn pointer() -> Primative {
 		Primative {
 			signed: false,
-			size: 2
+			size: 2,
+			fixed: false,
+		}
+	}
+
+	fn fixed_point() -> Primative {
+		Primative {
+			signed: true,
+			size: 2,
+			fixed: true,
 		}
 	}
 }
@@ -180,9 +595,27 @@ impl fmt::Display for Primative {
 
 struct TypeTable {
 	table: HashMap<String, Type>,
+	/// The file and span of each type defined by a script, unlike the builtin ones.
+	definitions: HashMap<String, (String, std::ops::Range<usize>)>,
 }
 
 impl TypeTable {
+	/// Fails if a new type may not be named `name`.
+	fn check_redefinition(&self, name: &str, span: std::ops::Range<usize>, file: &str) -> Result<(), CompilerError> {
+		if let Some((previous_file, previous)) = self.definitions.get(name) {
+			return Err(redefinition_error(format!("Type {name} is already defined"), span, file, previous.clone(), previous_file));
+		}
+		if self.table.contains_key(name) {
+			return Err(CompilerError::spanned(span.start, span.end, format!("{name} is a builtin type and cannot be redefined")));
+		}
+		Ok(())
+	}
+
+	fn define(&mut self, name: String, t: Type, span: std::ops::Range<usize>, file: &str) {
+		self.definitions.insert(name.clone(), (String::from(file), span));
+		self.table.insert(name, t);
+	}
+
 	fn lookup_type(&self, name: &str) -> Result<Type, String> {
 		match self.table.get(name) {
 			Some(t) => Ok(t.clone()),
@@ -190,6 +623,10 @@ impl TypeTable {
 		}
 	}
 
+	fn size_of(&self, name: &str) -> Result<i64, String> {
+		Ok(self.lookup_type(name)?.size() as i64)
+	}
+
 	fn lookup_primative(&self, name: &str) -> Result<Primative, String> {
 		match self.table.get(name) {
 			Some(t) => {
@@ -211,9 +648,26 @@ struct Variable {
 	scope_level: u32,
 }
 
+/// The number of single-character insertions, deletions and substitutions which turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+	let b = b.chars().collect::<Vec<char>>();
+	let mut previous = (0..=b.len()).collect::<Vec<usize>>();
+
+	for (i, a) in a.chars().enumerate() {
+		let mut current = vec![i + 1];
+		for (j, b) in b.iter().enumerate() {
+			let substitution = previous[j] + usize::from(a != *b);
+			current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+		}
+		previous = current;
+	}
+
+	previous[b.len()]
+}
+
 #[derive(Debug)]
 struct VariableTable {
-	peak_usage: u8,
+	peak_usage: usize,
 	// Used to free variables by scope.
 	scope_level: u32,
 	variables: [Option<Variable>; 256],
@@ -251,8 +705,10 @@ impl VariableTable {
 		while i < 256 {
 			match &self.variables[i] {
 				Some(var) => i += var.t.size() as usize,
+				// The variable must fit before the next one, which may have been reserved.
+				None if !(i..i + t.size() as usize).all(|j| j < 256 && self.variables[j].is_none()) => i += 1,
 				None => {
-					let this_peak = i as u8 + t.size();
+					let this_peak = i + t.size() as usize;
 					if self.peak_usage < this_peak {
 						self.peak_usage = this_peak;
 					}
@@ -271,6 +727,34 @@ impl VariableTable {
 		Err(String::from("Out of variable space; a single function is limited to 256 bytes"))
 	}
 
+	fn reserve(&mut self, reservation: &Reservation) {
+		let start = reservation.offset as usize;
+		let end = start + reservation.t.size() as usize;
+		if self.peak_usage < end {
+			self.peak_usage = end;
+		}
+
+		match &reservation.name {
+			Some(name) => {
+				self.variables[start] = Some(Variable {
+					name: Some(name.clone()),
+					t: reservation.t.clone(),
+					scope_level: 0,
+				});
+			}
+			// Nothing refers to an unnamed reservation as a whole, so each of its bytes is marked on its own.
+			None => {
+				for (offset, t) in reservation.t.primatives() {
+					self.variables[start + offset as usize] = Some(Variable {
+						name: None,
+						t: Type::Primative(t),
+						scope_level: 0,
+					});
+				}
+			}
+		}
+	}
+
 	fn free(&mut self, i: u8) {
 		assert!(self.variables[i as usize] != None, "Variable does not exist");
 		self.variables[i as usize] = None;
@@ -308,44 +792,30 @@ impl VariableTable {
 					if let Some(variable_name) = &variable.name {
 						if let Type::Struct(struct_type) = &variable.t {
 							if variable_name == components[0] {
-								// Now that we've found a struct, we'll traverse it to find the member.
-								let mut comp_i = 1;
-								'next_component: while comp_i < components.len() {
-									let mut offset = 0;
-
-									for (member_name, member) in struct_type {
-										if member_name == components[comp_i] {
-											match member {
-												Type::Primative(primative) => {
-													if comp_i + 1 != components.len() {
-														return Err(format!("{member_name} is a {primative} and has no members"))
-													}
-													return Ok((i + offset) as u8);
-												}
-												Type::Pointer(..) => {
-													if comp_i + 1 != components.len() {
-														return Err(format!("{member_name} is a pointer and has no members"))
-													}
-													return Ok((i + offset) as u8);
-												}
-												Type::Struct(..) => {
-													if comp_i + 1 == components.len() {
-														return Ok((i + offset) as u8);
-													} else {
-														comp_i += 1;
-														continue 'next_component;
-													}
-												}
-											}
-										}
-
-										offset += member.size() as usize;
+								// Now that we've found a struct, we'll traverse it to find the member,
+								// accumulating the offset of each struct along the way.
+								let mut struct_type = struct_type;
+								let mut offset = 0;
+								let mut parent = components[0];
+
+								for (comp_i, component) in components.iter().enumerate().skip(1) {
+									let Some(index) = struct_type.iter().position(|(member_name, _)| member_name == component) else {
+										return Err(format!("{component} is not a member of {parent}"));
+									};
+									offset += struct_type[..index].iter().map(|(_, member)| member.size() as usize).sum::<usize>();
+									let (member_name, member) = &struct_type[index];
+
+									let is_last = comp_i + 1 == components.len();
+									match member {
+										Type::Struct(members) if !is_last => struct_type = members,
+										_ if is_last => return Ok((i + offset) as u8),
+										Type::Primative(primative) => return Err(format!("{member_name} is a {primative} and has no members")),
+										_ => return Err(format!("{member_name} is a pointer and has no members")),
 									}
-									break;
+									parent = member_name;
 								}
-								return Err(format!("{name} is not a member of {variable_name}"))
 							}
-						} else {
+						} else if variable_name == components[0] {
 							return Err(format!("{} is not a struct", components[0]));
 						}
 					}
@@ -356,7 +826,37 @@ impl VariableTable {
 			}
 		}
 
-		Err(format!("Variable {name} does not exist"))
+		match self.suggest(name) {
+			Some(suggestion) => Err(format!("Variable {name} does not exist; did you mean {suggestion}?")),
+			None => Err(format!("Variable {name} does not exist")),
+		}
+	}
+
+	/// Like `lookup`, but an error points at `start..end`, where the name is written.
+	fn lookup_at(&self, name: &str, start: usize, end: usize) -> Result<u8, CompilerError> {
+		self.lookup(name).map_err(|msg| CompilerError::spanned(start, end, msg))
+	}
+
+	/// Finds the variable whose name is closest to `name`, if any is close enough to be a likely typo.
+	fn suggest(&self, name: &str) -> Option<&str> {
+		self.variables.iter()
+			.flatten()
+			.filter_map(|variable| variable.name.as_deref())
+			.map(|variable_name| (edit_distance(name, variable_name), variable_name))
+			.filter(|(distance, _)| *distance > 0 && distance * 3 <= name.len())
+			.min_by_key(|(distance, _)| *distance)
+			.map(|(_, variable_name)| variable_name)
+	}
+
+	/// Changes the type of the temporary at `i`, which must be the same size.
+	fn retype(&mut self, i: u8, t: Type) {
+		match &mut self.variables[i as usize] {
+			Some(var) => {
+				assert!(var.t.size() == t.size(), "Variable index {i} cannot change size");
+				var.t = t;
+			}
+			None => panic!("Variable index {i} does not exist"),
+		}
 	}
 
 	fn name_of(&mut self, i: u8) -> &mut Option<String> {
@@ -366,6 +866,28 @@ impl VariableTable {
 		}
 	}
 
+	/// Returns the full type of the variable beginning at `id`, if any.
+	fn variable_type(&self, id: u8) -> Option<&Type> {
+		self.variables[id as usize].as_ref().map(|var| &var.t)
+	}
+
+	/// Returns the type of the variable or member `name` if it is a struct.
+	fn struct_type(&self, name: &str) -> Option<&Type> {
+		let mut t = self.variable_type(self.lookup(root_variable(name)).ok()?)?;
+		for component in name.split('.').skip(1) {
+			let Type::Struct(members) = t else { return None };
+			t = &members.iter().find(|(member_name, _)| member_name == component)?.1;
+		}
+		match t {
+			Type::Struct(..) => Some(t),
+			_ => None,
+		}
+	}
+
+	fn is_struct(&self, rpn: &Rpn) -> bool {
+		matches!(rpn, Rpn::Variable(name) if self.struct_type(name).is_some())
+	}
+
 	fn is_pointer(&self, id: u8) -> bool {
 		let id = id as usize;
 
@@ -449,6 +971,23 @@ impl VariableTable {
 		panic!("Variable index {id} does not exist");
 	}
 
+	/// Counts the variables, named or temporary, currently occupying the pool.
+	fn live_count(&self) -> usize {
+		let mut count = 0;
+		let mut i = 0;
+
+		while i < 256 {
+			if let Some(variable) = &self.variables[i] {
+				count += 1;
+				i += variable.t.size() as usize;
+			} else {
+				i += 1;
+			}
+		}
+
+		count
+	}
+
 	fn push_scope(&mut self) {
 		self.scope_level += 1;
 	}
@@ -471,173 +1010,986 @@ impl VariableTable {
 	}
 }
 
-fn compile_environment<W: Write>(
-	this_name: &str,
-	env: types::Environment,
-	environment_table: &EnvironmentTable,
-	output: &mut W,
-) -> Result<Environment, CompilerError> {
-	let mut compiled_env = Environment {
-		name: String::from(this_name),
-		definitions: HashMap::<String, types::Definition>::new(),
-		pool: 0,
-	};
+/// Pointers to differently-sized objects may not be assigned to one another without a cast.
+/// Plain integers (such as the result of `&label`) are always accepted.
+fn check_pointer_assignment(
+	dest: &Type,
+	source: Option<&Type>,
+	options: &CompilerOptions,
+	function: &mut FunctionState,
+) -> Result<(), CompilerError> {
+	if let (Type::Pointer(dest_object), Some(Type::Pointer(source_object))) = (dest, source) {
+		if dest_object != source_object {
+			let msg = String::from("Assigning between incompatible pointer types; use a pointer cast if this is intended");
+			if options.strict_types {
+				return Err(CompilerError::from(msg));
+			}
+			function.warnings.push(CompilerError::from(msg));
+		}
+	}
 
-	let mut bytecode_index: u8 = 0;
+	Ok(())
+}
 
-	for i in env.contents {
-		match i.t {
-			StatementType::Use(name) => {
-				let other_env = match environment_table.get(&name) {
-					Some(other_env) => other_env,
-					None => return Err(CompilerError {
-						start: Some(i.start),
-						end: Some(i.end),
-						msg: format!("Environment {name} does not exist")
-					}),
-				};
+/// Finds assignments whose value is always overwritten before it is read, within a block of statements.
+///
+/// Only straight-line code is followed: anything mentioned by a nested block counts as read,
+/// and variables whose address is taken are never reported, since they may be read through it.
+#[derive(Default)]
+struct DeadStores {
+	/// The span of each variable's last store, if nothing has read it since.
+	pending: HashMap<String, (usize, usize)>,
+	escaped: HashSet<String>,
+	/// The span of each dead store, and the variable it was to.
+	found: Vec<(usize, usize, String)>,
+}
 
-				let mut greatest_bytecode = bytecode_index;
+impl DeadStores {
+	fn find(contents: &[Statement]) -> Vec<(usize, usize, String)> {
+		let mut dead_stores = DeadStores::default();
+		dead_stores.block(contents);
+		dead_stores.found
+	}
 
-				for (def_name, def) in &other_env.definitions {
-					if compiled_env.definitions.get(def_name).is_some() {
-						eprintln!("WARN: duplicate definition of {def_name} inside `use` statement.");
+	fn block(&mut self, contents: &[Statement]) {
+		for i in contents {
+			let span = (i.start, i.end);
+			match &i.t {
+				StatementType::Expression(rpn) => self.expression(rpn, span),
+				StatementType::Declaration(_, name) | StatementType::PointerDeclaration(_, _, name) => {
+					self.pending.remove(name);
+				}
+				// An initializer is part of the declaration, like the zero of `u8 x = 0;`, so replacing it is fine.
+				StatementType::DeclareAssign(_, name, rpn) | StatementType::PointerDeclareAssign(_, _, name, rpn) => {
+					self.expression(rpn, span);
+					self.pending.remove(name);
+				}
+				StatementType::DeclareInitialize(_, name, values) => {
+					for value in values {
+						self.expression(value, span);
 					}
-					
-					let mut new_def = def.clone();
+					self.pending.remove(name);
+				}
+				_ => {
+					let mut expressions = Vec::new();
+					let mut blocks = Vec::new();
+					nested_contents(i, &mut expressions, &mut blocks);
 
-					match new_def {
-						types::Definition::Def(ref mut sub_def) => {
-							sub_def.bytecode = bytecode_index.checked_add(sub_def.bytecode)
-								.ok_or(format!("Hit bytecode limit in environment {this_name}"))?;
-							writeln!(output, "def {this_name}@{def_name} equ {}", sub_def.bytecode)?;
-							if sub_def.bytecode > greatest_bytecode {
-								greatest_bytecode = sub_def.bytecode;
-							}
-						}
-						_ => {}
+					for rpn in expressions {
+						self.mention(rpn);
+					}
+					for block in blocks {
+						self.mention_block(block);
+
+						let mut inner = DeadStores {
+							escaped: self.escaped.clone(),
+							..Default::default()
+						};
+						inner.block(block);
+						self.found.extend(inner.found);
 					}
-
-					compiled_env.definitions.insert(def_name.clone(), new_def);
 				}
+			}
+		}
+	}
 
-				bytecode_index = greatest_bytecode;
+	fn expression(&mut self, rpn: &Rpn, span: (usize, usize)) {
+		match rpn {
+			Rpn::Variable(name) => self.read(name),
+			Rpn::Address(name) => {
+				self.read(name);
+				self.escaped.insert(String::from(root_variable(name)));
 			}
-			StatementType::Definition(name, mut def) => {
-				if compiled_env.definitions.get(&name).is_some() {
-					eprintln!("WARN: duplicate definition of {name}");
+			Rpn::Set(name, value, ..) => {
+				self.expression(value, span);
+				if self.escaped.contains(root_variable(name)) {
+					return;
 				}
-				match def {
-					types::Definition::Def(ref mut sub_def) => {
-						writeln!(output, "def {this_name}@{name} equ {bytecode_index}")?;
-						sub_def.bytecode = bytecode_index;
-						bytecode_index = bytecode_index.checked_add(1)
-							.ok_or(format!("Hit bytecode limit in environment {this_name}"))?;
-					}
-					_ => {}
+				if let Some((start, end)) = self.pending.insert(name.clone(), span) {
+					self.found.push((start, end, name.clone()));
+				}
+			}
+			_ => {
+				for i in rpn.operands() {
+					self.expression(i, span);
 				}
+			}
+		}
+	}
 
-				compiled_env.definitions.insert(name.clone(), def);
+	fn mention_block(&mut self, contents: &[Statement]) {
+		for i in contents {
+			let mut expressions = Vec::new();
+			let mut blocks = Vec::new();
+			nested_contents(i, &mut expressions, &mut blocks);
+
+			for rpn in expressions {
+				self.mention(rpn);
 			}
-			StatementType::Pool(expression) => {
-				let pool_size = expression.eval_const()?;
+			for block in blocks {
+				self.mention_block(block);
+			}
+		}
+	}
 
-				compiled_env.pool = if pool_size < 0 {
-					return Err(CompilerError::from("Pool size may not be negative"));
-				} else if pool_size > 256 {
-					return Err(CompilerError::from("Pool size is limited to 256 bytes"));
-				} else {
-					pool_size as u16
-				};
+	/// Treats every variable in an expression as read, including those it assigns to.
+	fn mention(&mut self, rpn: &Rpn) {
+		match rpn {
+			Rpn::Variable(name) | Rpn::Set(name, ..) => self.read(name),
+			Rpn::Address(name) => {
+				self.read(name);
+				self.escaped.insert(String::from(root_variable(name)));
 			}
-			_ => return Err(CompilerError::from(format!("StatementType {i:?} is not allowed within environments."))),
+			_ => {}
+		}
+		for i in rpn.operands() {
+			self.mention(i);
 		}
 	}
 
-	Ok(compiled_env)
+	fn read(&mut self, name: &str) {
+		// Reading a struct reads each of its members, and reading a member reads the store to the whole struct.
+		self.pending.retain(|stored, _| {
+			!(stored == name
+				|| stored.strip_prefix(name).is_some_and(|rest| rest.starts_with('.'))
+				|| name.strip_prefix(stored.as_str()).is_some_and(|rest| rest.starts_with('.')))
+		});
+	}
 }
 
-/// Compiles an Rpn tree, returning a variable containing the final result.
-fn compile_expression<W: Write>(
-	rpn: Rpn,
-	env: &Environment,
-	type_table: &TypeTable,
-	vtable: &mut VariableTable,
-	str_table: &mut Vec<String>,
-	output: &mut W
-) -> Result<Option<u8>, CompilerError> {
-	fn binary_operation<W: Write>(
-		l: Box<Rpn>,
-		op: &str,
-		r: Box<Rpn>,
-		env: &Environment,
-		type_table: &TypeTable,
-		vtable: & mut VariableTable,
-		str_table: &mut Vec<String>,
-		output: &mut W
-	) -> Result<Option<u8>, CompilerError> {
-		let l = compile_expression(*l, env, type_table, vtable, str_table, output)?
-			.ok_or(String::from("Expression has no return value"))?;
-		let r = compile_expression(*r, env, type_table, vtable, str_table, output)?
-			.ok_or(String::from("Expression has no return value"))?;
+/// Collects every `data` statement within a block, however deeply nested.
+fn data_statements<'a>(contents: &'a [Statement], data: &mut Vec<&'a Statement>) {
+	for i in contents {
+		if let StatementType::Data(..) = i.t {
+			data.push(i);
+			continue;
+		}
+		let mut expressions = Vec::new();
+		let mut blocks = Vec::new();
+		nested_contents(i, &mut expressions, &mut blocks);
+		for block in blocks {
+			data_statements(block, data);
+		}
+	}
+}
 
-		let result_type = Primative::from(vtable.type_of(l), vtable.type_of(r));
-		let result = vtable.alloc(Type::Primative(result_type))?;
-		// TODO: make opcodes consider operation size.
+/// Whether a block has an integer constant, or a constant part of an expression, which doesn't fit in a byte.
+/// Data tables are left out, since their values are never put into variables.
+fn needs_wide_integers(contents: &[Statement], defines: &HashMap<String, i64>) -> bool {
+	contents.iter().any(|i| {
+		if let StatementType::Data(..) = i.t {
+			return false;
+		}
+		let mut expressions = Vec::new();
+		let mut blocks = Vec::new();
+		nested_contents(i, &mut expressions, &mut blocks);
+		expressions.into_iter().any(|rpn| has_wide_constant(rpn, defines))
+			|| blocks.into_iter().any(|block| needs_wide_integers(block, defines))
+	})
+}
 
-		writeln!(output, "\tdb {}, {l}, {r}, {result}", env.expand(&format!("{op}_{result_type}"))?)?;
+fn has_wide_constant(rpn: &Rpn, defines: &HashMap<String, i64>) -> bool {
+	// The raw value of a fixed-point constant isn't the number it stands for.
+	let wide = !has_fixed(rpn) && rpn.eval_const_defined(defines).is_ok_and(|value| !(-0x80..=0xFF).contains(&value));
+	wide || rpn.operands().into_iter().any(|i| has_wide_constant(i, defines))
+}
 
-		vtable.autofree(l);
-		vtable.autofree(r);
+fn has_fixed(rpn: &Rpn) -> bool {
+	matches!(rpn, Rpn::Fixed(..)) || rpn.operands().into_iter().any(has_fixed)
+}
 
-		Ok(Some(result))
+/// Compiles the `data` tables of a function, which are placed after it where they aren't executed.
+/// Their labels are local to the function, and may be referred to as `&Function.label`.
+fn compile_function_data(contents: &[Statement], options: &CompilerOptions, function: &mut FunctionState) -> Result<(), CompilerError> {
+	let mut data = Vec::new();
+	data_statements(contents, &mut data);
+
+	for i in data {
+		let StatementType::Data(label, size, values) = &i.t else { unreachable!() };
+		let operands = compile_data(*size, values, options).map_err(|err| CompilerError::spanned(i.start, i.end, err.msg))?;
+		if let Some(label) = label {
+			function.data.push(Op::Label(format!(".{label}")));
+		}
+		function.data.push(data_op(*size, &operands, options));
 	}
 
-	fn compile_arguments<W: Write>(
-		def_args: &Vec<types::DefinitionParam>,
-		args: &Vec<Rpn>,
-		return_id: Option<u8>,
-		env: &Environment,
-		type_table: &TypeTable,
-		vtable: &mut VariableTable,
-		str_table: &mut Vec<String>,
-		output: &mut W
-	) -> Result<Vec<String>, CompilerError> {
-		let mut index = 0;
-		let mut arg_ids = Vec::<String>::new();
-		let mut to_free = Vec::<u8>::new();
-
+	Ok(())
+}
+
+/// The variable a (possibly struct member) name belongs to.
+fn root_variable(name: &str) -> &str {
+	name.split('.').next().unwrap_or(name)
+}
+
+/// Collects the expressions and blocks of statements directly within a statement.
+fn nested_contents<'a>(statement: &'a Statement, expressions: &mut Vec<&'a Rpn>, blocks: &mut Vec<&'a [Statement]>) {
+	match &statement.t {
+		StatementType::If(condition, contents, else_contents) => {
+			expressions.push(condition);
+			blocks.push(contents);
+			if let Some(else_contents) = else_contents {
+				blocks.push(else_contents);
+			}
+		}
+		StatementType::While(condition, contents) | StatementType::Do(condition, contents) => {
+			expressions.push(condition);
+			blocks.push(contents);
+		}
+		StatementType::For(prologue, condition, epilogue, contents) => {
+			nested_contents(prologue, expressions, blocks);
+			expressions.push(condition);
+			nested_contents(epilogue, expressions, blocks);
+			blocks.push(contents);
+		}
+		StatementType::Repeat(count, contents, _) => {
+			expressions.push(count);
+			blocks.push(contents);
+		}
+		StatementType::Loop(contents) => blocks.push(contents),
+		StatementType::Switch(value, cases, default) => {
+			expressions.push(value);
+			for case in cases {
+				expressions.extend(&case.values);
+				blocks.push(&case.contents);
+			}
+			if let Some(default) = default {
+				blocks.push(default);
+			}
+		}
+		StatementType::Expression(rpn)
+		| StatementType::DeclareAssign(_, _, rpn)
+		| StatementType::PointerDeclareAssign(_, _, _, rpn) => expressions.push(rpn),
+		StatementType::DeclareInitialize(_, _, values) | StatementType::Data(_, _, values) => expressions.extend(values),
+		_ => {}
+	}
+}
+
+/// The two bytes of a 16-bit operand, in the target's byte order.
+fn word(value: &str, options: &CompilerOptions) -> Vec<String> {
+	if options.big_endian {
+		vec![format!("HIGH({value})"), format!("LOW({value})")]
+	} else {
+		vec![format!("LOW({value})"), format!("HIGH({value})")]
+	}
+}
+
+/// The operand of a jump to `label`: its address, or with `relative_jumps` its offset from the jump.
+fn jump_target(label: &str, options: &CompilerOptions) -> Vec<String> {
+	if options.relative_jumps {
+		// Within a `db`, `@` is the address of its first byte, which is the jump's opcode.
+		word(&format!("{label} - @"), options)
+	} else {
+		word(label, options)
+	}
+}
+
+/// The bytes of a constant operand of up to 32 bits, in the target's byte order.
+fn const_bytes(value: &str, size: u8, options: &CompilerOptions) -> Vec<String> {
+	let mut bytes = (0..size).map(|i| match i {
+		0 => format!("{value} & $FF"),
+		1 if size == 2 => format!("{value} >> 8"),
+		_ => format!("({value} >> {}) & $FF", i * 8),
+	}).collect::<Vec<String>>();
+	if options.big_endian {
+		bytes.reverse();
+	}
+	bytes
+}
+
+/// Where the low and high bytes of a 16-bit variable are, relative to its first byte.
+fn byte_offsets(options: &CompilerOptions) -> (&'static str, &'static str) {
+	if options.big_endian {
+		(" + 1", "")
+	} else {
+		("", " + 1")
+	}
+}
+
+/// Something which is emitted as one or more operands of an instruction.
+trait Operands {
+	fn push_to(self, operands: &mut Vec<String>);
+}
+
+impl Operands for String {
+	fn push_to(self, operands: &mut Vec<String>) {
+		operands.push(self);
+	}
+}
+
+impl Operands for &str {
+	fn push_to(self, operands: &mut Vec<String>) {
+		operands.push(String::from(self));
+	}
+}
+
+impl Operands for &String {
+	fn push_to(self, operands: &mut Vec<String>) {
+		operands.push(self.clone());
+	}
+}
+
+impl Operands for u8 {
+	fn push_to(self, operands: &mut Vec<String>) {
+		operands.push(self.to_string());
+	}
+}
+
+impl Operands for i64 {
+	fn push_to(self, operands: &mut Vec<String>) {
+		operands.push(self.to_string());
+	}
+}
+
+impl Operands for Vec<String> {
+	fn push_to(self, operands: &mut Vec<String>) {
+		operands.extend(self);
+	}
+}
+
+/// Hands an instruction to a backend, with each operand expression flattened into its bytes.
+macro_rules! instruction {
+	($output:expr, $opcode:expr $(, $operand:expr)* $(,)?) => {{
+		#[allow(unused_mut)]
+		let mut operands = Vec::<String>::new();
+		$(Operands::push_to($operand, &mut operands);)*
+		$output.instruction(&$opcode, &strs(&operands))
+	}};
+}
+
+/// Fails with the assertion's message if its condition is false.
+fn check_static_assert(condition: &Rpn, message: Option<String>, type_table: &TypeTable, options: &CompilerOptions) -> Result<(), String> {
+	if condition.eval_const_in(&|t| type_table.size_of(t), &options.defines)? == 0 {
+		return Err(message.unwrap_or(String::from("Static assertion failed")));
+	}
+	Ok(())
+}
+
+/// Evaluates the contents of a `data` or `data16` statement into a list of operands.
+fn compile_data(size: u8, values: &[Rpn], options: &CompilerOptions) -> Result<Vec<String>, CompilerError> {
+	let (min, max) = match size {
+		1 => (-0x80, 0xFF),
+		_ => (-0x8000, 0xFFFF),
+	};
+	let mut operands = Vec::<String>::new();
+
+	for i in values {
+		// Label addresses are left for the linker to compute.
+		if let (2, Some((label, offset))) = (size, i.label_offset()) {
+			operands.push(match offset {
+				0 => label,
+				offset if offset < 0 => format!("{label} - {}", -offset),
+				offset => format!("{label} + {offset}"),
+			});
+			continue;
+		}
+
+		let value = i.eval_const_defined(&options.defines)?;
+		if value < min || value > max {
+			return Err(CompilerError::from(format!("{value} does not fit in {} bits", size * 8)));
+		}
+		operands.push(value.to_string());
+	}
+
+	Ok(operands)
+}
+
+/// The operand bytes of a constant put into a variable of type `t`.
+fn immediate(value: &str, t: Primative, options: &CompilerOptions) -> Vec<String> {
+	match t.size {
+		1 => vec![String::from(value)],
+		size => const_bytes(value, size, options),
+	}
+}
+
+/// The data emitting the operands of a `data` or `data16` statement.
+fn data_op(size: u8, operands: &[String], options: &CompilerOptions) -> Op {
+	if size == 2 && options.big_endian {
+		// `dw` is always little-endian.
+		Op::Data { size: 1, values: operands.iter().flat_map(|i| word(i, options)).collect() }
+	} else {
+		Op::Data { size, values: operands.to_vec() }
+	}
+}
+
+/// Lists each definition that was never used, by any environment, with the environment where it was defined.
+fn unused_definitions(environment_table: &EnvironmentTable) -> Vec<(String, String)> {
+	// Definitions are used under the name of whichever environment the function was in.
+	let mut used = HashSet::<(&str, &str)>::new();
+	for env in environment_table.values() {
+		for name in env.used.borrow().iter() {
+			if let Some((name, origin)) = env.origins.get_key_value(name.as_str()) {
+				used.insert((origin, name));
+			}
+		}
+	}
+
+	let mut environments = environment_table.values().collect::<Vec<&Environment>>();
+	environments.sort_by(|a, b| a.name.cmp(&b.name));
+
+	let mut result = Vec::new();
+	for env in environments {
+		let mut unused = env.definitions.iter()
+			.filter_map(|(name, def)| match def {
+				types::Definition::Def(def) if env.origins[name] == env.name => Some((def.bytecode, name)),
+				_ => None,
+			})
+			.filter(|(_, name)| !used.contains(&(env.name.as_str(), name.as_str())))
+			.collect::<Vec<(u8, &String)>>();
+		unused.sort();
+
+		for (_, name) in unused {
+			result.push((env.name.clone(), name.clone()));
+		}
+	}
+	result
+}
+
+/// Writes a comment describing an environment's pool: its size, and the regions set aside by `reserve`.
+fn write_pool_map<B: EmitBackend>(env: &Environment, output: &mut B) -> Result<(), CompilerError> {
+	output.directive("")?;
+	output.directive(&format!("; Pool of {}: {} bytes", env.name, env.pool))?;
+
+	let mut reservations = env.reservations.iter().collect::<Vec<&Reservation>>();
+	reservations.sort_by_key(|reservation| reservation.offset);
+	for reservation in reservations {
+		let end = reservation.offset as usize + reservation.t.size() as usize - 1;
+		let name = reservation.name.as_deref().unwrap_or("reserved");
+		output.directive(&format!(";   ${:02X}-${end:02X}: {name}", reservation.offset))?;
+	}
+	Ok(())
+}
+
+/// Writes a table of handlers indexed by bytecode, for the driver to dispatch through.
+/// Each handler is named `evs_{env}_{definition}`, and must be defined by the user;
+/// bytecodes without a definition point to `evs_unimplemented` instead.
+fn compile_dispatch_table<B: EmitBackend>(env: &Environment, output: &mut B) -> Result<(), CompilerError> {
+	output.directive("")?;
+	output.directive(&format!("section \"{} evscript dispatch table\", rom0, ALIGN[1]", env.name))?;
+	output.label(&format!("evs_{}_dispatch::", env.name))?;
+	for handler in env.bytecode_names() {
+		match handler {
+			Some(name) => output.data(2, &[&format!("evs_{}_{name}", env.name)])?,
+			None => output.data(2, &["evs_unimplemented"])?,
+		}
+	}
+
+	Ok(())
+}
+
+fn reservation_offset(offset: &Rpn, options: &CompilerOptions) -> Result<u8, String> {
+	let offset = offset.eval_const_defined(&options.defines)?;
+	u8::try_from(offset).map_err(|_| format!("Reserved offset {offset} is outside of the pool"))
+}
+
+/// Replaces the `feature` blocks of an environment with their contents if they are enabled.
+/// The definitions of disabled features are recorded so that using them gives a helpful error.
+fn enabled_statements(
+	contents: Vec<Statement>,
+	options: &CompilerOptions,
+	disabled: &mut HashMap<String, String>,
+) -> Vec<Statement> {
+	let mut result = Vec::new();
+
+	for i in contents {
+		if let StatementType::Feature(feature, feature_contents) = i.t {
+			if options.features.contains(&feature) {
+				result.extend(enabled_statements(feature_contents, options, disabled));
+			} else {
+				for j in feature_contents {
+					if let StatementType::Definition(name, _) = j.t {
+						disabled.insert(name, feature.clone());
+					}
+				}
+			}
+		} else {
+			result.push(i);
+		}
+	}
+
+	result
+}
+
+fn compile_environment<B: EmitBackend>(
+	this_name: &str,
+	env: types::Environment,
+	state: &CompilerState,
+	file: &str,
+	output: &mut B,
+	warnings: &mut Vec<CompilerError>,
+) -> Result<Environment, CompilerError> {
+	let CompilerState { options, environment_table, type_table, .. } = state;
+	let mut compiled_env = Environment {
+		name: String::from(this_name),
+		definitions: HashMap::<String, types::Definition>::new(),
+		pool: 0,
+		uses: Vec::new(),
+		origins: HashMap::new(),
+		version: None,
+		null: None,
+		default_int: None,
+		reservations: Vec::new(),
+		disabled: HashMap::new(),
+		used: RefCell::new(HashSet::new()),
+		file: String::from(file),
+		start: env.start,
+		end: env.end,
+	};
+
+	let mut bytecode_index: u8 = 0;
+	let mut alias_spans = Vec::<(String, usize, usize)>::new();
+
+	for i in enabled_statements(env.contents, options, &mut compiled_env.disabled) {
+		match i.t {
+			StatementType::Use(name) => {
+				let other_env = match environment_table.get(&name) {
+					Some(other_env) => other_env,
+					None => return Err(CompilerError::spanned(i.start, i.end, format!("Environment {name} does not exist"))),
+				};
+
+				let mut greatest_bytecode = bytecode_index;
+
+				compiled_env.uses.push(name.clone());
+				compiled_env.uses.extend(other_env.uses.iter().cloned());
+				compiled_env.disabled.extend(other_env.disabled.iter().map(|(k, v)| (k.clone(), v.clone())));
+				if other_env.null.is_some() {
+					compiled_env.null = other_env.null;
+				}
+				if other_env.default_int.is_some() {
+					compiled_env.default_int = other_env.default_int;
+				}
+
+				for reservation in &other_env.reservations {
+					compiled_env.reserve(reservation.clone()).map_err(|msg| CompilerError::spanned(i.start, i.end, msg))?;
+				}
+
+				for (def_name, def) in &other_env.definitions {
+					let origin = &other_env.origins[def_name];
+
+					// The definition from the environment used last replaces the earlier one.
+					if let Some(previous) = compiled_env.origins.get(def_name) {
+						if previous != origin {
+							warnings.push(CompilerError::spanned(i.start, i.end, format!(
+								"{def_name} is defined by both {previous} and {origin}; the definition from {origin} is used"
+							)));
+						}
+					}
+
+					let mut new_def = def.clone();
+
+					match new_def {
+						types::Definition::Def(ref mut sub_def) => {
+							sub_def.bytecode = bytecode_index.checked_add(sub_def.bytecode)
+								.ok_or(format!("Hit bytecode limit in environment {this_name}"))?;
+							output.directive(&format!("def {this_name}@{def_name} equ {}", sub_def.bytecode))?;
+							if sub_def.bytecode > greatest_bytecode {
+								greatest_bytecode = sub_def.bytecode;
+							}
+						}
+						_ => {}
+					}
+
+					compiled_env.definitions.insert(def_name.clone(), new_def);
+					compiled_env.origins.insert(def_name.clone(), origin.clone());
+				}
+
+				bytecode_index = greatest_bytecode;
+			}
+			StatementType::Definition(name, mut def) => {
+				if compiled_env.definitions.get(&name).is_some() {
+					warnings.push(CompilerError::spanned(i.start, i.end, format!("duplicate definition of {name}")));
+				}
+				match def {
+					types::Definition::Def(ref mut sub_def) => {
+						output.directive(&format!("def {this_name}@{name} equ {bytecode_index}"))?;
+						sub_def.bytecode = bytecode_index;
+						bytecode_index = bytecode_index.checked_add(1)
+							.ok_or(format!("Hit bytecode limit in environment {this_name}"))?;
+					}
+					types::Definition::Alias(ref alias) => {
+						// Arguments are numbered from $1, so that the checks at each call can't fail.
+						for param in &alias.target_args {
+							if let types::AliasParam::ArgId(index) = param {
+								if *index == 0 || *index > alias.args.len() {
+									return Err(CompilerError::spanned(i.start, i.end, match alias.args.len() {
+										0 => format!("{name} takes no arguments, so there is no ${index}"),
+										count => format!("The arguments of {name} are numbered $1 to ${count}, so there is no ${index}"),
+									}));
+								}
+							}
+						}
+						alias_spans.push((name.clone(), i.start, i.end));
+					}
+					_ => {}
+				}
+
+				compiled_env.origins.insert(name.clone(), String::from(this_name));
+				compiled_env.definitions.insert(name, def);
+			}
+			StatementType::Pool(expression) => {
+				let pool_size = expression.eval_const_defined(&options.defines)?;
+
+				compiled_env.pool = if pool_size < 0 {
+					return Err(CompilerError::from("Pool size may not be negative"));
+				} else if pool_size > 256 {
+					return Err(CompilerError::from("Pool size is limited to 256 bytes"));
+				} else {
+					pool_size as u16
+				};
+			}
+			StatementType::Reserve(size, offset) => {
+				let size = size.eval_const_defined(&options.defines)?;
+				// Reserving the whole pool would leave no room for any variable.
+				if !(1..256).contains(&size) {
+					return Err(CompilerError::from(format!("Cannot reserve {size} bytes")));
+				}
+				let byte = (String::new(), Type::Primative(Primative::default_integer()));
+
+				compiled_env.reserve(Reservation {
+					name: None,
+					offset: reservation_offset(&offset, options)?,
+					t: Type::Struct(vec![byte; size as usize]),
+				}).map_err(|msg| CompilerError::spanned(i.start, i.end, msg))?;
+			}
+			StatementType::ReserveVariable(t, name, offset) => {
+				compiled_env.reserve(Reservation {
+					name: Some(name),
+					offset: reservation_offset(&offset, options)?,
+					t: type_table.lookup_type(&t)?,
+				}).map_err(|msg| CompilerError::spanned(i.start, i.end, msg))?;
+			}
+			StatementType::Version(expression) => {
+				compiled_env.version = Some(expression.eval_const_defined(&options.defines)?);
+			}
+			StatementType::Null(expression) => {
+				let value = expression.eval_const_defined(&options.defines)?;
+				compiled_env.null = Some(u16::try_from(value)
+					.map_err(|_| CompilerError::from(format!("Null value {value} does not fit in a pointer")))?);
+			}
+			StatementType::DefaultInt(t) => {
+				let default_int = type_table.lookup_primative(&t)
+					.ok()
+					.filter(|t| !t.fixed)
+					.ok_or(CompilerError::spanned(i.start, i.end, format!("{t} is not an integer type")))?;
+				compiled_env.default_int = Some(default_int);
+			}
+			_ => return Err(CompilerError::from(format!("StatementType {i:?} is not allowed within environments."))),
+		}
+	}
+
+	if let (None, Some(t)) = (compiled_env.default_int, &options.default_int) {
+		let default_int = type_table.lookup_primative(t)
+			.ok()
+			.filter(|t| !t.fixed)
+			.ok_or(CompilerError::from(format!("{t} is not an integer type")))?;
+		compiled_env.default_int = Some(default_int);
+	}
+
+	// Aliases inherited through `use` were already checked by the environment that defined them.
+	for (name, start, end) in alias_spans {
+		check_alias_chain(&compiled_env, &name).map_err(|msg| CompilerError::spanned(start, end, msg))?;
+	}
+
+	Ok(compiled_env)
+}
+
+/// Follows an alias through its targets, making sure it ends at a bytecode definition.
+fn check_alias_chain(env: &Environment, name: &str) -> Result<(), String> {
+	let mut chain = vec![name];
+	let mut current = name;
+
+	while let Some(types::Definition::Alias(alias)) = env.definitions.get(current) {
+		current = &alias.target;
+		if chain.contains(&current) {
+			chain.push(current);
+			return Err(format!("Alias cycle: {}", chain.join(" -> ")));
+		}
+		chain.push(current);
+	}
+
+	match env.definitions.get(current) {
+		Some(types::Definition::Def(..)) => Ok(()),
+		Some(types::Definition::Macro(..)) => Err(format!("{name} is an alias of {current}, which is a macro")),
+		Some(types::Definition::Alias(..)) => unreachable!(),
+		// Using the alias reports the missing feature, so it may still be defined.
+		None if env.disabled.contains_key(current) => Ok(()),
+		None => Err(format!("{name} is an alias of {current}, which is not defined")),
+	}
+}
+
+/// Extends a byte to the size of a 16-bit operation, so that its other operand is read correctly.
+/// Fixed-point values are left alone, since converting to them is a matter of scale, not size.
+fn widen<B: EmitBackend>(
+	id: u8,
+	operation_type: Primative,
+	env: &Environment,
+	options: &CompilerOptions,
+	vtable: &mut VariableTable,
+	output: &mut B,
+) -> Result<u8, CompilerError> {
+	let narrow_type = vtable.type_of(id);
+	if narrow_type.size >= operation_type.size || narrow_type.fixed || operation_type.fixed {
+		return Ok(id);
+	}
+
+	let wide = vtable.alloc(Type::Primative(Primative { size: operation_type.size, ..narrow_type }))?;
+	let (low, high) = byte_offsets(options);
+	instruction!(output, env.expand("mov_u8")?, format!("{wide}{low}"), id)?;
+	if narrow_type.signed {
+		// Shifting a signed byte right by 7 leaves $FF if it is negative and 0 otherwise: its high byte.
+		let scratch = vtable.alloc(Type::Primative(Primative::default_integer()))?;
+		instruction!(output, env.expand("put_u8")?, scratch, "7")?;
+		instruction!(output, env.expand("shr_i8")?, id, scratch, format!("{wide}{high}"))?;
+		vtable.autofree(scratch);
+	} else {
+		instruction!(output, env.expand("put_u8")?, format!("{wide}{high}"), "0")?;
+	}
+
+	vtable.autofree(id);
+	Ok(wide)
+}
+
+/// Converts an integer to an 8.8 fixed-point number, shifting it into the high byte.
+/// Only the low byte of a wider integer fits.
+fn to_fixed<B: EmitBackend>(
+	id: u8,
+	env: &Environment,
+	options: &CompilerOptions,
+	vtable: &mut VariableTable,
+	output: &mut B,
+) -> Result<u8, CompilerError> {
+	let integer_type = vtable.type_of(id);
+	let (low, high) = byte_offsets(options);
+	let integer_low = if integer_type.size > 1 { low } else { "" };
+
+	let result = vtable.alloc(Type::Primative(Primative::fixed_point()))?;
+	instruction!(output, env.expand("put_u8")?, format!("{result}{low}"), "0")?;
+	instruction!(output, env.expand("mov_u8")?, format!("{result}{high}"), format!("{id}{integer_low}"))?;
+
+	vtable.autofree(id);
+	Ok(result)
+}
+
+/// Shifts an 8.8 fixed-point number by 4 bits into a temporary, so that multiplying or dividing it doesn't overflow.
+fn shift_fixed<B: EmitBackend>(
+	id: u8,
+	op: &str,
+	env: &Environment,
+	options: &CompilerOptions,
+	vtable: &mut VariableTable,
+	output: &mut B,
+) -> Result<u8, CompilerError> {
+	// Both operands of a 16-bit shift are 16-bit.
+	let scratch = vtable.alloc(Type::Primative(Primative { signed: false, size: 2, fixed: false }))?;
+	let result = vtable.alloc(Type::Primative(Primative::fixed_point()))?;
+	let (low, high) = byte_offsets(options);
+	instruction!(output, env.expand("put_u8")?, format!("{scratch}{low}"), "4")?;
+	instruction!(output, env.expand("put_u8")?, format!("{scratch}{high}"), "0")?;
+	instruction!(output, env.expand(&format!("{op}_{}", Primative::fixed_point()))?, id, scratch, result)?;
+
+	vtable.autofree(scratch);
+	vtable.autofree(id);
+	Ok(result)
+}
+
+/// Converts the value in `id` to `dest_type`, widening or truncating integers as needed.
+/// Returns `id` itself if no conversion is needed, or a temporary if one is.
+fn convert<B: EmitBackend>(
+	id: u8,
+	dest_type: Primative,
+	env: &Environment,
+	options: &CompilerOptions,
+	vtable: &mut VariableTable,
+	output: &mut B,
+) -> Result<u8, CompilerError> {
+	let source_type = vtable.type_of(id);
+	if source_type == dest_type {
+		return Ok(id);
+	}
+	if dest_type.fixed {
+		return to_fixed(id, env, options, vtable, output);
+	}
+	if source_type.fixed {
+		// The integer part of a fixed-point number is its high byte.
+		let (_, high) = byte_offsets(options);
+		let integer = vtable.alloc(Type::Primative(Primative { signed: true, size: 1, fixed: false }))?;
+		instruction!(output, env.expand("mov_u8")?, integer, format!("{id}{high}"))?;
+		vtable.autofree(id);
+		return convert(integer, dest_type, env, options, vtable, output);
+	}
+
+	let result = if source_type.size < dest_type.size {
+		widen(id, dest_type, env, options, vtable, output)?
+	} else if source_type.size > dest_type.size || vtable.name_of(id).is_some() {
+		// Only the low byte of a wider value is kept.
+		let result = vtable.alloc(Type::Primative(dest_type))?;
+		let (low, _) = byte_offsets(options);
+		let low = if dest_type.size < source_type.size { low } else { "" };
+		instruction!(output, env.expand(&format!("mov_{dest_type}"))?, result, format!("{id}{low}"))?;
+		vtable.autofree(id);
+		result
+	} else {
+		id
+	};
+
+	// Only the signedness may still differ, which changes how the bytes are read, not what they are.
+	vtable.retype(result, Type::Primative(dest_type));
+	Ok(result)
+}
+
+/// What the expressions and statements of a function are compiled against.
+#[derive(Clone, Copy)]
+struct Context<'a> {
+	env: &'a Environment,
+	type_table: &'a TypeTable,
+	function_table: &'a FunctionTable,
+	options: &'a CompilerOptions,
+}
+
+/// Compiles an Rpn tree whose result is used, returning the variable containing it.
+fn compile_value<B: EmitBackend>(
+	rpn: Rpn,
+	ctx: &Context,
+	vtable: &mut VariableTable,
+	function: &mut FunctionState,
+	output: &mut B
+) -> Result<u8, CompilerError> {
+	let error = match &rpn {
+		Rpn::Call(name, _, start, end) => CompilerError::spanned(*start, *end, format!("{name} does not return a value, so its result cannot be used")),
+		_ => CompilerError::from("Expression has no return value"),
+	};
+	compile_expression(rpn, ctx, vtable, function, output)?
+		.ok_or(error)
+}
+
+/// Checks an operation between two constants which may fail, such as a division by zero.
+/// The parser leaves these unfolded, so the error is reported here rather than at runtime.
+fn check_constant_operation(
+	l: &Rpn,
+	r: &Rpn,
+	operation: fn(i64, i64) -> Result<i64, String>,
+	options: &CompilerOptions
+) -> Result<(), CompilerError> {
+	if let (Ok(l), Ok(r)) = (l.eval_const_defined(&options.defines), r.eval_const_defined(&options.defines)) {
+		operation(l, r)?;
+	}
+	Ok(())
+}
+
+/// Compiles an Rpn tree, returning a variable containing the final result.
+fn compile_expression<B: EmitBackend>(
+	rpn: Rpn,
+	ctx: &Context,
+	vtable: &mut VariableTable,
+	function: &mut FunctionState,
+	output: &mut B
+) -> Result<Option<u8>, CompilerError> {
+	fn binary_operation<B: EmitBackend>(
+		l: Box<Rpn>,
+		op: &str,
+		r: Box<Rpn>,
+		ctx: &Context,
+		vtable: & mut VariableTable,
+		function: &mut FunctionState,
+		output: &mut B
+	) -> Result<Option<u8>, CompilerError> {
+		let &Context { env, options, .. } = ctx;
+		let l = compile_value(*l, ctx, vtable, function, output)?;
+		let r = compile_value(*r, ctx, vtable, function, output)?;
+
+		let mut operation_type = Primative::from(vtable.type_of(l), vtable.type_of(r));
+		// A right shift sign-extends only if the value being shifted is signed;
+		// the signedness of the shift amount is irrelevant.
+		if op == "shr" {
+			operation_type.signed = vtable.type_of(l).signed;
+		}
+		let (mut l, mut r) = (l, r);
+		if operation_type.fixed {
+			// An integer operand is converted, so that `x + 1` adds 1.0 rather than 1/256.
+			if !vtable.type_of(l).fixed {
+				l = to_fixed(l, env, options, vtable, output)?;
+			}
+			if !vtable.type_of(r).fixed {
+				r = to_fixed(r, env, options, vtable, output)?;
+			}
+			// The product of two 8.8 numbers has 16 fractional bits, and a quotient has none,
+			// so 4 bits are moved out of the way beforehand to keep the result in 8.8 within 16 bits.
+			// This gives up the lowest 4 bits of precision of each operand.
+			match op {
+				"mul" => {
+					l = shift_fixed(l, "shr", env, options, vtable, output)?;
+					r = shift_fixed(r, "shr", env, options, vtable, output)?;
+				}
+				"div" => {
+					l = shift_fixed(l, "shl", env, options, vtable, output)?;
+					r = shift_fixed(r, "shr", env, options, vtable, output)?;
+				}
+				_ => {}
+			}
+		}
+		let l = widen(l, operation_type, env, options, vtable, output)?;
+		let r = widen(r, operation_type, env, options, vtable, output)?;
+		// Comparisons only ever produce 0 or 1, so a single byte is enough regardless of the operands' size.
+		let result_type = match op {
+			"equ" | "nequ" | "lt" | "gt" | "lte" | "gte" | "land" | "lor" => Primative { signed: false, size: 1, fixed: false },
+			_ => operation_type,
+		};
+		let result = vtable.alloc(Type::Primative(result_type))?;
+
+		instruction!(output, env.expand(&format!("{op}_{operation_type}"))?, l, r, result)?;
+
+		vtable.autofree(l);
+		vtable.autofree(r);
+
+		Ok(Some(result))
+	}
+
+	fn compile_arguments<B: EmitBackend>(
+		def_args: &Vec<types::DefinitionParam>,
+		args: &Vec<Rpn>,
+		return_id: Option<u8>,
+		ctx: &Context,
+		vtable: &mut VariableTable,
+		function: &mut FunctionState,
+		output: &mut B
+	) -> Result<Vec<Vec<String>>, CompilerError> {
+		let &Context { type_table, options, .. } = ctx;
+		let mut index = 0;
+		// The operand bytes of each argument.
+		let mut arg_ids = Vec::<Vec<String>>::new();
+		let mut to_free = Vec::<u8>::new();
+
 		for i in def_args {
 			match i {
 				types::DefinitionParam::Type(t) => {
-					let this_arg = compile_expression(args[index].clone(), env, type_table, vtable, str_table, output)?
-						.ok_or(String::from("Expression has no return value"))?;
+					let this_arg = compile_value(args[index].clone(), ctx, vtable, function, output)?;
 
 					if let Type::Primative(t) = type_table.lookup_type(&t)? {
 						if t != vtable.type_of(this_arg) {
-							eprintln!("WARN: argument type does not match definition");
+							function.warnings.push(CompilerError::from("argument type does not match definition"));
 						}
 					}
 
-					arg_ids.push(this_arg.to_string());
+					arg_ids.push(vec![this_arg.to_string()]);
 					// Free this temporary once all arguments are processed.
 					to_free.push(this_arg);
 					index += 1;
 				}
+				types::DefinitionParam::Pointer(t) => {
+					let this_arg = compile_value(args[index].clone(), ctx, vtable, function, output)?;
+
+					let param_type = Type::Pointer(Box::new(type_table.lookup_type(t)?));
+					check_pointer_assignment(&param_type, vtable.variable_type(this_arg), options, function)?;
+
+					arg_ids.push(vec![this_arg.to_string()]);
+					to_free.push(this_arg);
+					index += 1;
+				}
 				types::DefinitionParam::Const(t) => {
 					if let Type::Primative(t) = type_table.lookup_type(&t)? {
 						match &args[index] {
 							Rpn::Signed(value) => match t.size {
-								1 => arg_ids.push(value.to_string()),
-								2 => {
-									arg_ids.push(format!("{value} & $FF, {value} >> 8"));
-								}
-								3 => {
-									arg_ids.push(format!("{value} & $FF, ({value} >> 8) & $FF, ({value} >> 16) & $FF"));
-								}
-								4 => {
-									arg_ids.push(format!("{value} & $FF, ({value} >> 8) & $FF, ({value} >> 16) & $FF, ({value} >> 24) & $FF"));
-								}
+								1 => arg_ids.push(vec![value.to_string()]),
+								2..=4 => arg_ids.push(const_bytes(&value.to_string(), t.size, options)),
 								_ => panic!("Invalid size {}, only up to 32 bits are supported", t.size),
 							}
 							Rpn::String(text) => {
@@ -645,25 +1997,22 @@ fn compile_expression<W: Write>(
 									return Err(CompilerError::from("A string must be 16-bit"));
 								}
 
-								let value = format!(".__string{}", str_table.len());
-								str_table.push(text.clone());
-								arg_ids.push(format!("LOW({value}), HIGH({value})"));
+								let value = function.string_label(text)?;
+								arg_ids.push(word(&value, options));
 							}
-							Rpn::Variable(value) => match t.size {
-								1 => arg_ids.push(value.to_string()),
-								2 => {
-									arg_ids.push(format!("{value} & $FF, {value} >> 8"));
-								}
-								3 => {
-									arg_ids.push(format!("{value} & $FF, ({value} >> 8) & $FF, ({value} >> 16) & $FF"));
-								}
-								4 => {
-									arg_ids.push(format!("{value} & $FF, ({value} >> 8) & $FF, ({value} >> 16) & $FF, ({value} >> 24) & $FF"));
-								}
+							Rpn::Variable(value) if !options.defines.contains_key(value) => match t.size {
+								1 => arg_ids.push(vec![value.to_string()]),
+								2..=4 => arg_ids.push(const_bytes(&value.to_string(), t.size, options)),
 								_ => panic!("Invalid size {}, only up to 32 bits are supported", t.size),
 							}
-							_ => {
-								return Err(CompilerError::from("Expression must be constant"))
+							// Anything else is folded, such as `2 * 8` or `sizeof(Player)`.
+							rpn => {
+								let value = rpn.eval_const_in(&|t| type_table.size_of(t), &options.defines)?;
+								match t.size {
+									1 => arg_ids.push(vec![value.to_string()]),
+									2..=4 => arg_ids.push(const_bytes(&value.to_string(), t.size, options)),
+									_ => panic!("Invalid size {}, only up to 32 bits are supported", t.size),
+								}
 							}
 						}
 					} else {
@@ -672,7 +2021,7 @@ fn compile_expression<W: Write>(
 					index += 1;
 				}
 				types::DefinitionParam::Return(..) => {
-					arg_ids.push(return_id.unwrap().to_string());
+					arg_ids.push(vec![return_id.unwrap().to_string()]);
 				}
 			}
 		}
@@ -694,7 +2043,7 @@ fn compile_expression<W: Write>(
 
 		for i in args {
 			match i {
-				types::DefinitionParam::Type(..) | types::DefinitionParam::Const (..) => {
+				types::DefinitionParam::Type(..) | types::DefinitionParam::Pointer(..) | types::DefinitionParam::Const (..) => {
 					def_arg_count += 1;
 				}
 				types::DefinitionParam::Return(t) => {
@@ -709,55 +2058,144 @@ fn compile_expression<W: Write>(
 		Ok((def_arg_count, return_id))
 	}
 
+	let &Context { env, type_table, function_table, options } = ctx;
 	match rpn {
 		Rpn::Variable(name) => {
 			match vtable.lookup(&name) {
 				Ok(i) => Ok(Some(i)),
+				// A missing member of a variable which does exist is never meant as a symbol.
+				Err(msg) if name.contains('.') && vtable.lookup(root_variable(&name)).is_ok() => Err(CompilerError::from(msg)),
+				Err(..) if options.defines.contains_key(&name) => {
+					compile_expression(Rpn::Signed(options.defines[&name]), ctx, vtable, function, output)
+				}
 				Err(..) => {
-					let result_type = Primative::default_integer();
+					// Anything which isn't a variable is assumed to be a symbol, which makes typos easy to miss.
+					if let Some(suggestion) = vtable.suggest(&name) {
+						function.warnings.push(CompilerError::from(format!(
+							"{name} is not a variable, so it is used as a symbol; did you mean {suggestion}?"
+						)));
+					}
+					let result_type = function.int_type;
 					let result = vtable.alloc(Type::Primative(result_type))?;
 					// put (result), value
-					writeln!(output, "\tdb {}, {result}, {name}", env.expand(&format!("put_{result_type}"))?)?;
+					instruction!(output, env.expand(&format!("put_{result_type}"))?, result, immediate(&name, result_type, options))?;
 					Ok(Some(result))
 				}
 			}
 		}
 		Rpn::Address(name) => {
 			match vtable.lookup(&name) {
-				Ok(..) => {
-					return Err(CompilerError::from("Cannot take the address of a local variable!"));
+				Ok(i) => {
+					// Variables have no absolute address, so this is their offset within the pool,
+					// which only the VM can turn into a real address.
+					let target = match vtable.variable_type(i) {
+						Some(t) => t.clone(),
+						None => Type::Primative(vtable.type_of(i)),
+					};
+					let result = vtable.alloc(Type::pointer_to(target, 1))?;
+					let (low, high) = byte_offsets(options);
+					instruction!(output, env.expand("put_u8")?, format!("{result}{low}"), i)?;
+					instruction!(output, env.expand("put_u8")?, format!("{result}{high}"), "0")?;
+					Ok(Some(result))
 				},
 				Err(..) => {
 					let result_type = Primative::pointer();
 					let result = vtable.alloc(Type::Primative(result_type))?;
 					// put (result), value
-					writeln!(output, "\tdb {}, {result}, {name} & $FF", env.expand(&format!("put_u8"))?)?;
-					writeln!(output, "\tdb {}, {result} + 1, {name} >> 8", env.expand(&format!("put_u8"))?)?;
+					let (low, high) = byte_offsets(options);
+					instruction!(output, env.expand("put_u8")?, format!("{result}{low}"), format!("{name} & $FF"))?;
+					instruction!(output, env.expand("put_u8")?, format!("{result}{high}"), format!("{name} >> 8"))?;
 					Ok(Some(result))
 				}
 			}
 		}
 		Rpn::Signed(value) => {
-			// The "default" type of an integer is u8 (think C's int)
+			// The "default" type of an integer is u8 (think C's int), unless the environment or function needs another.
 			// This is because most projects will probably only have the 8-bit bytecode installed.
-			// TODO: make the default integer type configurable
-			let result_type = Primative { signed: false, size: 1 };
-			let result = vtable.alloc(Type::Primative(result_type))?;
+			let result_type = function.int_type;
+			if !(-0x8000..=0xFFFF).contains(&value) {
+				return Err(CompilerError::from(format!("{value} does not fit in 16 bits")));
+			}
+			if result_type.size == 1 && !(-0x80..=0xFF).contains(&value) {
+				// Only a constant which doesn't fit in a byte needs 16 bits, which then carry through the expression.
+				let result = vtable.alloc(Type::Primative(Primative { signed: value < 0, size: 2, fixed: false }))?;
+				let (low, high) = byte_offsets(options);
+				instruction!(output, env.expand("put_u8")?, format!("{result}{low}"), format!("LOW({value})"))?;
+				instruction!(output, env.expand("put_u8")?, format!("{result}{high}"), format!("HIGH({value})"))?;
+				return Ok(Some(result));
+			}
+			// A negative constant is signed, so that it is sign-extended if it is widened,
+			// but it is put the same way as an unsigned one.
+			let result = vtable.alloc(Type::Primative(Primative { signed: result_type.signed || value < 0, ..result_type }))?;
 			// put (result), value
-			writeln!(output, "\tdb {}, {result}, {value}", env.expand(&format!("put_{result_type}"))?)?;
+			instruction!(output, env.expand(&format!("put_{result_type}"))?, result, immediate(&value.to_string(), result_type, options))?;
+			Ok(Some(result))
+		}
+		Rpn::Fixed(value) => {
+			let result = vtable.alloc(Type::Primative(Primative::fixed_point()))?;
+			let (low, high) = byte_offsets(options);
+			instruction!(output, env.expand("put_u8")?, format!("{result}{low}"), format!("LOW({value})"))?;
+			instruction!(output, env.expand("put_u8")?, format!("{result}{high}"), format!("HIGH({value})"))?;
 			Ok(Some(result))
 		}
 		Rpn::String(string) => {
-			let result_type = Primative { signed: false, size: 2 };
+			let result_type = Primative { signed: false, size: 2, fixed: false };
 			let result = vtable.alloc(Type::Primative(result_type))?;
-			let value = format!(".__string{}", str_table.len());
+			let value = function.string_label(&string)?;
 			// TODO: make this a 16-bit put
-			writeln!(output, "\tdb {}, {result}, LOW({value})", env.expand(&format!("put_u8"))?)?;
-			writeln!(output, "\tdb {}, {result} + 1, HIGH({value})", env.expand(&format!("put_u8"))?)?;
-			str_table.push(string);
+			let (low, high) = byte_offsets(options);
+			instruction!(output, env.expand("put_u8")?, format!("{result}{low}"), format!("LOW({value})"))?;
+			instruction!(output, env.expand("put_u8")?, format!("{result}{high}"), format!("HIGH({value})"))?;
 			Ok(Some(result))
 		}
-		Rpn::Call(name, args) => {
+		Rpn::SizeOf(t) => {
+			compile_expression(Rpn::Signed(type_table.size_of(&t)?), ctx, vtable, function, output)
+		}
+		Rpn::Null => {
+			// Like an address, null is just a u16, so that it may be assigned to or compared with any pointer.
+			let value = env.null.unwrap_or(0);
+			let result = vtable.alloc(Type::Primative(Primative::pointer()))?;
+			let (low, high) = byte_offsets(options);
+			instruction!(output, env.expand("put_u8")?, format!("{result}{low}"), format!("LOW({value})"))?;
+			instruction!(output, env.expand("put_u8")?, format!("{result}{high}"), format!("HIGH({value})"))?;
+			Ok(Some(result))
+		}
+		Rpn::StrLen(text) => {
+			compile_expression(Rpn::Signed(types::string_length(&text)? as i64), ctx, vtable, function, output)
+		}
+		Rpn::Call(name, args, ..) => {
+			if env.lookup(&name).is_err() {
+				if let Some(callee) = function_table.get(&name) {
+					if !env.is_compatible(&callee.environment) {
+						return Err(CompilerError::from(format!(
+							"Cannot call {name}: its environment {} is not compatible with {}",
+							callee.environment,
+							env.name
+						)));
+					}
+					if !args.is_empty() {
+						return Err(CompilerError::from(format!("{name} is a function and does not take arguments")));
+					}
+
+					// Functions without @bank may be placed in any bank, so only a
+					// call into a known shared bank (or the caller itself) can stay near.
+					let caller_bank = function_table.get(&function.name).and_then(|caller| caller.bank);
+					let same_bank = name == function.name
+						|| matches!((caller_bank, callee.bank), (Some(caller), Some(callee)) if caller == callee);
+					if options.far_calls || !same_bank {
+						instruction!(
+							output,
+							env.expand("farcall")?,
+							format!("BANK({name})"),
+							word(&name, options),
+						)?;
+					} else {
+						instruction!(output, env.expand("call")?, word(&name, options))?;
+					}
+					return Ok(None);
+				}
+			}
+
 			match env.lookup(&name)? {
 				types::Definition::Def(def) => {
 					let (def_arg_count, return_id) = validate_args(&def.args, type_table, vtable)?;
@@ -772,25 +2210,20 @@ fn compile_expression<W: Write>(
 						&def.args,
 						&args,
 						return_id,
-						env,
-						type_table,
+						ctx,
 						vtable,
-						str_table,
+						function,
 						output
 					)?;
 
-					write!(output, "\tdb {}", env.expand(&name)?)?;
-					for i in arg_ids {
-						write!(output, ", {i}")?;
-					}
-					writeln!(output, "")?;
+					instruction!(output, env.expand(&name)?, arg_ids.concat())?;
 
 					Ok(return_id)
 				}
 				types::Definition::Alias(def) => {
 					enum AliasVariant {
 						ArgId(usize),
-						ExpressionId(String),
+						ExpressionId(Vec<String>),
 					}
 
 					let (def_arg_count, return_id) = validate_args(&def.args, type_table, vtable)?;
@@ -805,10 +2238,9 @@ fn compile_expression<W: Write>(
 						&def.args,
 						&args,
 						return_id,
-						env,
-						type_table,
+						ctx,
 						vtable,
-						str_table,
+						function,
 						output
 					)?;
 
@@ -818,48 +2250,50 @@ fn compile_expression<W: Write>(
 						match i {
 							types::AliasParam::ArgId(index) => alias_ids.push(AliasVariant::ArgId(*index)),
 							types::AliasParam::Expression(rpn) => {
-								let this_arg = compile_expression(rpn.clone(), env, type_table, vtable, str_table, output)?
-									.ok_or(String::from("Expression has no return value"))?;
-								alias_ids.push(AliasVariant::ExpressionId(this_arg.to_string()));
+								let this_arg = compile_value(rpn.clone(), ctx, vtable, function, output)?;
+								alias_ids.push(AliasVariant::ExpressionId(vec![this_arg.to_string()]));
 								vtable.autofree(this_arg);
 							}
 							types::AliasParam::Const(rpn) => {
 								match rpn {
 									Rpn::Signed(value) => {
 										if *value >= 256 || *value < -128 {
-											return Err(CompilerError::from("WARN: integer constants can only be 8 bits"));
+											return Err(CompilerError::from(format!("{value} does not fit in 8 bits")));
 										}
-										alias_ids.push(AliasVariant::ExpressionId(value.to_string()));
+										alias_ids.push(AliasVariant::ExpressionId(vec![value.to_string()]));
 									}
 									Rpn::String(text) => {
-										let value = format!(".__string{}", str_table.len());
-										str_table.push(text.clone());
-										alias_ids.push(AliasVariant::ExpressionId(format!("LOW({value}), HIGH({value})")));
+										let value = function.string_label(text)?;
+										alias_ids.push(AliasVariant::ExpressionId(word(&value, options)));
 									}
-									Rpn::Variable(value) => {
-										alias_ids.push(AliasVariant::ExpressionId(value.to_string()));
+									Rpn::Variable(value) if !options.defines.contains_key(value) => {
+										alias_ids.push(AliasVariant::ExpressionId(vec![value.to_string()]));
 									}
-									_ => {
-										return Err(CompilerError::from("Expression must be constant"))
+									rpn => {
+										let value = rpn.eval_const_in(&|t| type_table.size_of(t), &options.defines)?;
+										if !(-128..256).contains(&value) {
+											return Err(CompilerError::from(format!("{value} does not fit in 8 bits")));
+										}
+										alias_ids.push(AliasVariant::ExpressionId(vec![value.to_string()]));
 									}
 								}
 							}
 						}
 					}
 
-					write!(output, "\tdb {}", env.expand(&name)?)?;
+					let mut operands = Vec::<String>::new();
 					for i in alias_ids {
 						match i {
-							AliasVariant::ExpressionId(index) => write!(output, ", {index}")?,
+							AliasVariant::ExpressionId(bytes) => operands.extend(bytes),
 							AliasVariant::ArgId(index) => {
 								if index > arg_ids.len() {
 									return Err(CompilerError::from(format!("Argument ID is too large ({index})")));
 								}
-								write!(output, ", {}", arg_ids[index - 1])?;
+								operands.extend(arg_ids[index - 1].iter().cloned());
 							}
 						}
 					}
-					writeln!(output, "")?;
+					instruction!(output, env.expand(&name)?, operands)?;
 
 					Ok(return_id)
 				}
@@ -876,32 +2310,31 @@ fn compile_expression<W: Write>(
 						&def.args,
 						&args,
 						return_id,
-						env,
-						type_table,
+						ctx,
 						vtable,
-						str_table,
+						function,
 						output
 					)?;
 
-					write!(output, "\t{}", def.target)?;
-					for i in arg_ids {
-						write!(output, " {i},")?;
+					env.used.borrow_mut().insert(name.clone());
+					if options.check_macros == Some(MacroCheck::Assert) {
+						output.directive(&format!("\tassert DEF({0}), \"Macro {0} is not defined\"", def.target))?;
 					}
-					writeln!(output, "")?;
+					let args = arg_ids.concat();
+					output.macro_invocation(&def.target, &strs(&args))?;
 
 					Ok(return_id)
 				}
 			}
 		}
 		Rpn::Negate(i) => {
-			let operand = compile_expression(*i, env, type_table, vtable, str_table, output)?
-				.ok_or(String::from("Expression has no return value"))?;
+			let operand = compile_value(*i, ctx, vtable, function, output)?;
 			let operand_type = vtable.type_of(operand);
 			let zero = vtable.alloc(Type::Primative(operand_type))?;
-			let result = vtable.alloc(Type::Primative(operand_type))?;
-			// TODO: make opcodes consider operand size.
-			writeln!(output, "\tdb {}, {zero}, $0", env.expand(&format!("put_{operand_type}"))?)?;
-			writeln!(output, "\tdb {}, {zero}, {operand}, {result}", env.expand(&format!("sub_{operand_type}"))?)?;
+			// A negated value is negative, even if its operand was unsigned.
+			let result = vtable.alloc(Type::Primative(Primative { signed: true, ..operand_type }))?;
+			instruction!(output, env.expand(&format!("put_{operand_type}"))?, zero, immediate("0", operand_type, options))?;
+			instruction!(output, env.expand(&format!("sub_{operand_type}"))?, zero, operand, result)?;
 
 			vtable.free(zero);
 			vtable.autofree(operand);
@@ -909,33 +2342,50 @@ fn compile_expression<W: Write>(
 			Ok(Some(result))
 		}
 		Rpn::Not(i) => {
-			let operand = compile_expression(*i, env, type_table, vtable, str_table, output)?
-				.ok_or(String::from("Expression has no return value"))?;
+			let operand = compile_value(*i, ctx, vtable, function, output)?;
 			let operand_type = vtable.type_of(operand);
-			// TODO: make the default integer type configurable per-environment
-			let ff = vtable.alloc(Type::Primative(operand_type))?;
+			let ones = vtable.alloc(Type::Primative(operand_type))?;
 			let result = vtable.alloc(Type::Primative(operand_type))?;
-			writeln!(output, "\tdb {}, {ff}, $FF", env.expand(&format!("put_{operand_type}"))?)?;
-			writeln!(output, "\tdb {}, {operand}, {ff}, {result}", env.expand(&format!("xor_{operand_type}"))?)?;
+			// Every bit of the operand is flipped, whatever its size.
+			let mask = format!("${}", "FF".repeat(operand_type.size as usize));
+			instruction!(output, env.expand(&format!("put_{operand_type}"))?, ones, immediate(&mask, operand_type, options))?;
+			instruction!(output, env.expand(&format!("bxor_{operand_type}"))?, operand, ones, result)?;
 
-			vtable.free(ff);
+			vtable.free(ones);
 			vtable.autofree(operand);
 
 			Ok(Some(result))
 		}
+		Rpn::PointerCast(t, i) => {
+			let source = compile_value(*i, ctx, vtable, function, output)?;
+
+			if vtable.type_of(source).size != Primative::pointer().size {
+				return Err(CompilerError::from(format!(
+					"Only {}-bit values may be cast to pointers",
+					Primative::pointer().size * 8
+				)));
+			}
+
+			let pointer_type = Type::Pointer(Box::new(type_table.lookup_type(&t)?));
+			let result = vtable.alloc(pointer_type)?;
+			instruction!(output, env.expand("mov_u16")?, result, source)?;
+
+			vtable.autofree(source);
+
+			Ok(Some(result))
+		}
 		Rpn::Deref(i) => {
-			let source = compile_expression(*i, env, type_table, vtable, str_table, output)?
-				.ok_or(String::from("Expression has no return value"))?;
+			let source = compile_value(*i, ctx, vtable, function, output)?;
 
 			if !vtable.is_pointer(source) {
 				return Err(CompilerError::from("Attempting to deref a non-pointer! Note that address-of returns a `u16`, not a `u16 ptr`. Try declaring the pointer before dereferencing."));
 			}
 
+			// Dereferencing peels off one level of pointers, so `[[pp]]` works on a `u8 ptr ptr`.
 			let source_type = match &vtable.variables[source as usize] {
 				Some(var) => match &var.t {
 					Type::Pointer(t) => match **t {
-						Type::Primative(t) => t,
-						Type::Pointer(..) => Primative::pointer(),
+						Type::Primative(..) | Type::Pointer(..) => (**t).clone(),
 						Type::Struct(..) => {
 							return Err(CompilerError::from("A pointer to a structure cannot be dereferenced. Try working with individual members."));
 						}
@@ -945,91 +2395,304 @@ fn compile_expression<W: Write>(
 				None => panic!(),
 			};
 
-			let dest = vtable.alloc(Type::Primative(source_type))?;
+			let dest = vtable.alloc(source_type)?;
 			let dest_type = vtable.type_of(dest);
 
-			writeln!(output, "\tdb {}, {dest}, {source}", env.expand(&format!("deref_{dest_type}"))?)?;
+			instruction!(output, env.expand(&format!("deref_{dest_type}"))?, dest, source)?;
 
 			vtable.autofree(source);
 
 			Ok(Some(dest))
 		}
-		Rpn::Mul(l, r) => binary_operation(l, "mul", r, env, type_table, vtable, str_table, output),
-		Rpn::Div(l, r) => binary_operation(l, "div", r, env, type_table, vtable, str_table, output),
-		Rpn::Mod(l, r) => binary_operation(l, "mod", r, env, type_table, vtable, str_table, output),
-		Rpn::Add(l, r) => binary_operation(l, "add", r, env, type_table, vtable, str_table, output),
-		Rpn::Sub(l, r) => binary_operation(l, "sub", r, env, type_table, vtable, str_table, output),
-		Rpn::ShiftLeft(l, r) => binary_operation(l, "shl", r, env, type_table, vtable, str_table, output),
-		Rpn::ShiftRight(l, r) => binary_operation(l, "shr", r, env, type_table, vtable, str_table, output),
-		Rpn::BinaryAnd(l, r) => binary_operation(l, "band", r, env, type_table, vtable, str_table, output),
-		Rpn::BinaryXor(l, r) => binary_operation(l, "bxor", r, env, type_table, vtable, str_table, output),
-		Rpn::BinaryOr(l, r) => binary_operation(l, "bor", r, env, type_table, vtable, str_table, output),
-		Rpn::Equ(l, r) => binary_operation(l, "equ", r, env, type_table, vtable, str_table, output),
-		Rpn::NotEqu(l, r) => binary_operation(l, "nequ", r, env, type_table, vtable, str_table, output),
-		Rpn::LessThan(l, r) => binary_operation(l, "lt", r, env, type_table, vtable, str_table, output),
-		Rpn::GreaterThan(l, r) => binary_operation(l, "gt", r, env, type_table, vtable, str_table, output),
-		Rpn::LessThanEqu(l, r) => binary_operation(l, "lte", r, env, type_table, vtable, str_table, output),
-		Rpn::GreaterThanEqu(l, r) => binary_operation(l, "gte", r, env, type_table, vtable, str_table, output),
-		Rpn::LogicalAnd(l, r) => binary_operation(l, "land", r, env, type_table, vtable, str_table, output),
-		Rpn::LogicalOr(l, r) => binary_operation(l, "lor", r, env, type_table, vtable, str_table, output),
-		Rpn::Set(name, i) => {
+		Rpn::Mul(l, r) => binary_operation(l, "mul", r, ctx, vtable, function, output),
+		Rpn::Div(l, r) => {
+			check_constant_operation(&l, &r, types::const_div, options)?;
+			binary_operation(l, "div", r, ctx, vtable, function, output)
+		}
+		Rpn::Mod(l, r) => {
+			check_constant_operation(&l, &r, types::const_mod, options)?;
+			binary_operation(l, "mod", r, ctx, vtable, function, output)
+		}
+		// An offset from a label can be computed by the linker rather than at runtime.
+		Rpn::Add(..) | Rpn::Sub(..) if rpn.label_offset().is_some() => {
+			let (label, offset) = rpn.label_offset().unwrap();
+			let (low, high) = byte_offsets(options);
+			if let Ok(i) = vtable.lookup(&label) {
+				// Like `&local` alone, this is an offset within the pool.
+				let target = match vtable.variable_type(i) {
+					Some(t) => t.clone(),
+					None => Type::Primative(vtable.type_of(i)),
+				};
+				let address = i as i64 + offset;
+				let result = vtable.alloc(Type::pointer_to(target, 1))?;
+				instruction!(output, env.expand("put_u8")?, format!("{result}{low}"), address & 0xFF)?;
+				instruction!(output, env.expand("put_u8")?, format!("{result}{high}"), (address >> 8) & 0xFF)?;
+				return Ok(Some(result));
+			}
+
+			let result = vtable.alloc(Type::Primative(Primative::pointer()))?;
+			instruction!(output, env.expand("put_u8")?, format!("{result}{low}"), format!("({label} + {offset}) & $FF"))?;
+			instruction!(output, env.expand("put_u8")?, format!("{result}{high}"), format!("({label} + {offset}) >> 8"))?;
+			Ok(Some(result))
+		}
+		Rpn::Add(l, r) => binary_operation(l, "add", r, ctx, vtable, function, output),
+		Rpn::Sub(l, r) => binary_operation(l, "sub", r, ctx, vtable, function, output),
+		Rpn::ShiftLeft(l, r) => {
+			check_constant_operation(&l, &r, types::const_shl, options)?;
+			binary_operation(l, "shl", r, ctx, vtable, function, output)
+		}
+		Rpn::ShiftRight(l, r) => {
+			check_constant_operation(&l, &r, types::const_shr, options)?;
+			binary_operation(l, "shr", r, ctx, vtable, function, output)
+		}
+		Rpn::BinaryAnd(l, r) => binary_operation(l, "band", r, ctx, vtable, function, output),
+		Rpn::BinaryXor(l, r) => binary_operation(l, "bxor", r, ctx, vtable, function, output),
+		Rpn::BinaryOr(l, r) => binary_operation(l, "bor", r, ctx, vtable, function, output),
+		Rpn::Equ(l, r) if vtable.is_struct(&l) || vtable.is_struct(&r) => compile_struct_comparison(&l, &r, true, ctx, vtable, function, output),
+		Rpn::NotEqu(l, r) if vtable.is_struct(&l) || vtable.is_struct(&r) => compile_struct_comparison(&l, &r, false, ctx, vtable, function, output),
+		Rpn::Equ(l, r) => binary_operation(l, "equ", r, ctx, vtable, function, output),
+		Rpn::NotEqu(l, r) => binary_operation(l, "nequ", r, ctx, vtable, function, output),
+		Rpn::LessThan(l, r) => binary_operation(l, "lt", r, ctx, vtable, function, output),
+		Rpn::GreaterThan(l, r) => binary_operation(l, "gt", r, ctx, vtable, function, output),
+		Rpn::LessThanEqu(l, r) => binary_operation(l, "lte", r, ctx, vtable, function, output),
+		Rpn::GreaterThanEqu(l, r) => binary_operation(l, "gte", r, ctx, vtable, function, output),
+		Rpn::LogicalAnd(l, r) => binary_operation(l, "land", r, ctx, vtable, function, output),
+		Rpn::LogicalOr(l, r) => binary_operation(l, "lor", r, ctx, vtable, function, output),
+		Rpn::ComparisonChain(operands, comparisons) => {
+			let l = function.expression_label;
+			function.expression_label += 1;
+
+			let result = vtable.alloc(Type::Primative(Primative { signed: false, size: 1, fixed: false }))?;
+			let mut operands = operands.into_iter();
+			let mut left = compile_value(operands.next().unwrap(), ctx, vtable, function, output)?;
+
+			for (i, comparison) in comparisons.iter().enumerate() {
+				let mut right = compile_value(operands.next().unwrap(), ctx, vtable, function, output)?;
+				let operation_type = Primative::from(vtable.type_of(left), vtable.type_of(right));
+				// The converted right operand holds the same value, so it is what the next comparison reads.
+				if operation_type.fixed {
+					if !vtable.type_of(left).fixed {
+						left = to_fixed(left, env, options, vtable, output)?;
+					}
+					if !vtable.type_of(right).fixed {
+						right = to_fixed(right, env, options, vtable, output)?;
+					}
+				}
+				left = widen(left, operation_type, env, options, vtable, output)?;
+				let right = widen(right, operation_type, env, options, vtable, output)?;
+
+				instruction!(
+					output,
+					env.expand(&format!("{}_{operation_type}", comparison.opcode()))?,
+					left,
+					right,
+					result,
+				)?;
+				vtable.autofree(left);
+				left = right;
+
+				// Stop at the first comparison that fails.
+				if i + 1 < comparisons.len() {
+					instruction!(
+						output,
+						env.expand("jmp_if_false")?,
+						result,
+						jump_target(&format!(".__chain{l}"), options),
+					)?;
+				}
+			}
+
+			vtable.autofree(left);
+			output.label(&format!(".__chain{l}"))?;
+			Ok(Some(result))
+		}
+		Rpn::Select(condition, then_value, else_value) => {
+			let l = function.expression_label;
+			function.expression_label += 1;
+
+			let condition = compile_value(*condition, ctx, vtable, function, output)?;
+			instruction!(
+				output,
+				env.expand("jmp_if_false")?,
+				condition,
+				jump_target(&format!(".__select_else{l}"), options),
+			)?;
+			vtable.autofree(condition);
+
+			// The type of the result depends on both values, so each branch is buffered
+			// until it is known, and only then moves its value into the result.
+			let mut then_output = Vec::<Op>::new();
+			let then_value = compile_value(*then_value, ctx, vtable, function, &mut then_output)?;
+			let mut else_output = Vec::<Op>::new();
+			let else_value = compile_value(*else_value, ctx, vtable, function, &mut else_output)?;
+
+			let result_type = Primative::from(vtable.type_of(then_value), vtable.type_of(else_value));
+			let result = vtable.alloc(Type::Primative(result_type))?;
+			let mov = env.expand(&format!("mov_{result_type}"))?;
+
+			let then_value = convert(then_value, result_type, env, options, vtable, &mut then_output)?;
+			let else_value = convert(else_value, result_type, env, options, vtable, &mut else_output)?;
+
+			for op in &then_output {
+				op.emit(output)?;
+			}
+			instruction!(output, mov, result, then_value)?;
+			instruction!(output, env.expand("jmp")?, jump_target(&format!(".__select_end{l}"), options))?;
+			output.label(&format!(".__select_else{l}"))?;
+			for op in &else_output {
+				op.emit(output)?;
+			}
+			instruction!(output, mov, result, else_value)?;
+			output.label(&format!(".__select_end{l}"))?;
+
+			vtable.autofree(then_value);
+			vtable.autofree(else_value);
+			Ok(Some(result))
+		}
+		Rpn::Set(name, i, start, end) => {
+			// In a chain such as `a = b = c`, `c` is evaluated once and moved into each variable, starting with `b`.
+			let mut names = vec![(name, start, end)];
+			let mut i = *i;
+			while let Rpn::Set(name, next, start, end) = i {
+				names.push((name, start, end));
+				i = *next;
+			}
+
 			// A plain Set may only assign to existing variables.
-			let dest = vtable.lookup(&name)?;
-			let dest_type = vtable.type_of(dest);
+			let dests = names.iter()
+				.map(|(name, start, end)| vtable.lookup_at(name, *start, *end))
+				.collect::<Result<Vec<u8>, CompilerError>>()?;
 			// TODO: make this directly take ownership of i if it is not an Rpn::Variable.
-			let source = compile_expression(*i, env, type_table, vtable, str_table, output)?
-				.ok_or(String::from("Expression has no return value"))?;
+			let mut source = compile_value(i, ctx, vtable, function, output)?;
+
+			for &dest in dests.iter().rev() {
+				let dest_type = vtable.type_of(dest);
+
+				if let Some(dest_var_type) = vtable.variable_type(dest) {
+					check_pointer_assignment(dest_var_type, vtable.variable_type(source), options, function)?;
+				}
+
+				// Like a declaration, the value takes the type of the variable, so that a byte fills a whole u16.
+				let value = convert(source, dest_type, env, options, vtable, output)?;
+				instruction!(output, env.expand(&format!("mov_{dest_type}"))?, dest, value)?;
+				vtable.autofree(value);
+
+				// As in C, each variable of a chain is given the value of the one after it, after its conversion.
+				source = dest;
+			}
+
+			Ok(Some(dests[0]))
+		}
+	}
+}
+
+/// Compares two structs of the same type member by member, stopping at the first member which settles the result.
+fn compile_struct_comparison<B: EmitBackend>(
+	l: &Rpn,
+	r: &Rpn,
+	equal: bool,
+	ctx: &Context,
+	vtable: &mut VariableTable,
+	function: &mut FunctionState,
+	output: &mut B,
+) -> Result<Option<u8>, CompilerError> {
+	let &Context { env, options, .. } = ctx;
+	let (Rpn::Variable(l_name), Rpn::Variable(r_name)) = (l, r) else {
+		return Err(CompilerError::from("A struct can only be compared with another struct variable"));
+	};
+	let members = match (vtable.struct_type(l_name), vtable.struct_type(r_name)) {
+		(Some(l_type), Some(r_type)) if l_type == r_type => l_type.primatives(),
+		(Some(..), Some(..)) => return Err(CompilerError::from(format!("{l_name} and {r_name} are structs of different types"))),
+		_ => return Err(CompilerError::from("A struct can only be compared with another struct of the same type")),
+	};
+	let l_id = vtable.lookup(l_name)?;
+	let r_id = vtable.lookup(r_name)?;
 
-			writeln!(output, "\tdb {}, {dest}, {source}", env.expand(&format!("mov_{dest_type}"))?)?;
+	let label = function.expression_label;
+	function.expression_label += 1;
 
-			vtable.autofree(source);
+	let result = vtable.alloc(Type::Primative(Primative { signed: false, size: 1, fixed: false }))?;
+	// `==` is settled by the first unequal member, and `!=` by the first member which differs.
+	let (op, jump) = if equal { ("equ", "jmp_if_false") } else { ("nequ", "jmp_if_true") };
 
-			Ok(Some(dest))
+	for (i, (offset, t)) in members.iter().enumerate() {
+		instruction!(output, env.expand(&format!("{op}_{t}"))?, l_id + offset, r_id + offset, result)?;
+		if i + 1 < members.len() {
+			instruction!(output, env.expand(jump)?, result, jump_target(&format!(".__compare{label}"), options))?;
 		}
 	}
+	output.label(&format!(".__compare{label}"))?;
+
+	Ok(Some(result))
+}
+
+/// Gives a variable its name, and with `--variable-equates`, lets the assembly that follows refer to its slot.
+fn name_variable<B: EmitBackend>(
+	id: u8,
+	name: String,
+	options: &CompilerOptions,
+	vtable: &mut VariableTable,
+	function: &FunctionState,
+	output: &mut B
+) -> Result<(), CompilerError> {
+	if options.variable_equates {
+		// Slots are reused across scopes, so the equate is redefined for each declaration.
+		output.directive(&format!("redef {}@{name} = {id}", function.name))?;
+	}
+	*vtable.name_of(id) = Some(name);
+	Ok(())
 }
 
-fn compile_statement<W: Write>(
+fn compile_statement<B: EmitBackend>(
 	statement: Statement,
-	env: &Environment,
-	type_table: &TypeTable,
+	ctx: &Context,
 	label_index: &mut u32,
 	vtable: &mut VariableTable,
-	str_table: &mut Vec<String>,
-	output: &mut W
+	function: &mut FunctionState,
+	output: &mut B
 ) -> Result<(), CompilerError> {
+	let &Context { env, type_table, options, .. } = ctx;
 	// Automatically adds statement.start and statement.end to a compiler error.
-	let statement_error = |msg: String| -> CompilerError {
-		CompilerError {
-			start: Some(statement.start),
-			end: Some(statement.end),
-			msg
-		}
+	let statement_error = |msg: String| CompilerError::spanned(statement.start, statement.end, msg);
+	// Errors which already point at part of the statement keep their location.
+	let in_statement = |err: CompilerError| match err.start {
+		Some(_) => err,
+		None => statement_error(err.msg),
 	};
 
+	if let Some(source) = &function.source {
+		output.directive(&format!("; {}:{}", source.file, source.line(statement.start)))?;
+	}
+
 	match statement.t {
 		StatementType::Expression(rpn) => {
-			if let Err(msg) = compile_expression(rpn, env, type_table, vtable, str_table, output) {
-				// TODO: Give Rpn nodes their own location info.
-				return Err(CompilerError {
-					start: Some(statement.start),
-					end: Some(statement.end),
-					msg: msg.to_string(),
-				} );
+			if let (Some(limit), Rpn::Call(name, ..)) = (options.yield_depth_limit, &rpn) {
+				let live = vtable.live_count();
+				if (name == "yld" || name == "yld_ret") && live > limit {
+					function.warnings.push(CompilerError::spanned(statement.start, statement.end, format!("yielding with {live} live variables, more than the limit of {limit}")));
+				}
 			}
+
+			// Calls and assignments are made for their side effects; anything else is computed for nothing.
+			if !matches!(rpn, Rpn::Call(..) | Rpn::Set(..)) {
+				function.warnings.push(CompilerError::spanned(statement.start, statement.end, String::from("the result of this expression is unused")));
+			}
+
+			compile_expression(rpn, ctx, vtable, function, output).map_err(in_statement)?;
 		}
 		StatementType::Declaration(t, name) => {
 			let new_var = vtable.alloc(type_table.lookup_type(&t)?)?;
-			*vtable.name_of(new_var) = Some(name);
+			name_variable(new_var, name, options, vtable, function, output)?;
 		}
-		StatementType::PointerDeclaration(t, name) => {
+		StatementType::PointerDeclaration(t, depth, name) => {
 			let object_type = type_table.lookup_type(&t)?;
-			let new_var = vtable.alloc(Type::Pointer(Box::new(object_type)))?;
-			*vtable.name_of(new_var) = Some(name);
+			let new_var = vtable.alloc(Type::pointer_to(object_type, depth))?;
+			name_variable(new_var, name, options, vtable, function, output)?;
 		}
 		StatementType::DeclareAssign(t, name, rpn) => {
 			match rpn {
-				Rpn::Variable(source_name) => {
+				// A `-D` definition is a constant, unless a variable shadows it.
+				Rpn::Variable(source_name) if vtable.lookup(&source_name).is_ok() || !options.defines.contains_key(&source_name) => {
 					// Create a new variable
 					let dest_type = match type_table.lookup_primative(&t) {
 						Ok(t) => t,
@@ -1037,111 +2700,187 @@ fn compile_statement<W: Write>(
 							"Cannot assign to structures, assign to individual members instead"
 						)))
 					};
-					let dest = vtable.alloc(Type::Primative(dest_type))?;
-					let source = vtable.lookup(&source_name)?;
-
-					writeln!(
-						output,
-						"\tdb {}, {dest}, {source}",
-						env.expand(&format!("mov_{dest_type}"))?
-					)?;
+					let source = vtable.lookup(&source_name).map_err(statement_error)?;
+
+					let source_type = vtable.type_of(source);
+					let dest = if source_type.size == dest_type.size && source_type.fixed == dest_type.fixed {
+						let dest = vtable.alloc(Type::Primative(dest_type))?;
+						instruction!(
+							output,
+							env.expand(&format!("mov_{dest_type}"))?,
+							dest,
+							source,
+						)?;
+						dest
+					} else {
+						convert(source, dest_type, env, options, vtable, output)?
+					};
 
-					*vtable.name_of(dest) = Some(name);
+					name_variable(dest, name, options, vtable, function, output)?;
 
 					vtable.autofree(source);
 				}
 				_ => {
-					let new_var = compile_expression(rpn, env, type_table, vtable, str_table, output)?
-						.ok_or(statement_error(String::from("Expression has no return value")))?;
-					*vtable.name_of(new_var) = Some(name);
+					let new_var = compile_value(rpn, ctx, vtable, function, output)
+						.map_err(in_statement)?;
+					// The declared type wins over that of the value, so that `i8 x = -3;` is signed.
+					let new_var = match type_table.lookup_primative(&t) {
+						Ok(dest_type) => convert(new_var, dest_type, env, options, vtable, output)?,
+						Err(..) => new_var,
+					};
+					name_variable(new_var, name, options, vtable, function, output)?;
 				}
 			}
 		},
-		StatementType::PointerDeclareAssign(t, name, rpn) => {
+		StatementType::DeclareInitialize(t, name, values) => {
+			let var_type = type_table.lookup_type(&t)?;
+			let members = var_type.primatives();
+
+			if values.len() > members.len() {
+				return Err(statement_error(format!(
+					"Too many initializers: {t} has {} members, but {} values were given",
+					members.len(),
+					values.len(),
+				)));
+			}
+
+			let dest = vtable.alloc(var_type)?;
+			name_variable(dest, name, options, vtable, function, output)?;
+
+			// Members without a value are zeroed.
+			let mut values = values.into_iter();
+			for (offset, member_type) in members {
+				let value = values.next().unwrap_or(Rpn::Signed(0));
+				let source = compile_value(value, ctx, vtable, function, output)
+					.map_err(in_statement)?;
+				let source = convert(source, member_type, env, options, vtable, output)?;
+
+				instruction!(
+					output,
+					env.expand(&format!("mov_{member_type}"))?,
+					dest + offset,
+					source,
+				)?;
+
+				vtable.autofree(source);
+			}
+		}
+		StatementType::PointerDeclareAssign(t, depth, name, rpn) => {
 			let dest_type = match type_table.lookup_primative(&t) {
-				Ok(t) => t,
+				Ok(t) => Type::pointer_to(Type::Primative(t), depth),
 				Err(..) => return Err(statement_error(String::from(
 					"Cannot assign to structures, assign to individual members instead"
 				)))
 			};
-			let dest = vtable.alloc(Type::Pointer(Box::new(Type::Primative(dest_type))))?;
-			*vtable.name_of(dest) = Some(name);
+			let dest = vtable.alloc(dest_type.clone())?;
+			name_variable(dest, name, options, vtable, function, output)?;
 
-			let source = compile_expression(rpn, env, type_table, vtable, str_table, output)?
-				.ok_or(statement_error(String::from("Expression has no return value")))?;
+			let source = compile_value(rpn, ctx, vtable, function, output)
+				.map_err(in_statement)?;
 
-			writeln!(output, "\tdb {}, {dest}, {source}", env.expand(&format!("mov_u16"))?)?;
+			check_pointer_assignment(
+				&dest_type,
+				vtable.variable_type(source),
+				options,
+				function,
+			).map_err(in_statement)?;
+
+			instruction!(output, env.expand("mov_u16")?, dest, source)?;
 
 			vtable.autofree(source);
 		},
+		// With --optimize, a constant condition leaves only the code that would run.
+		StatementType::If(condition, contents, else_contents) if options.optimize && condition.eval_const_defined(&options.defines).is_ok() => {
+			let taken = if condition.eval_const_defined(&options.defines)? != 0 {
+				contents
+			} else {
+				else_contents.unwrap_or_default()
+			};
+
+			vtable.push_scope();
+			for i in taken {
+				compile_statement(i, ctx, label_index, vtable, function, output)?;
+			}
+			vtable.pop_scope();
+		}
+		StatementType::While(condition, _) if options.optimize && condition.eval_const_defined(&options.defines) == Ok(0) => {}
+		StatementType::Do(condition, contents) if options.optimize && condition.eval_const_defined(&options.defines) == Ok(0) => {
+			vtable.push_scope();
+			for i in contents {
+				compile_statement(i, ctx, label_index, vtable, function, output)?;
+			}
+			vtable.pop_scope();
+		}
 		StatementType::If(condition, contents, else_contents) => {
-			let condition_result = compile_expression(condition, env, type_table, vtable, str_table, output)?
-				.ok_or(statement_error(String::from("Expression has no return value")))?;
+			let condition_result = compile_value(condition, ctx, vtable, function, output)
+				.map_err(in_statement)?;
 			let l = *label_index;
 			*label_index += 1;
 
-			writeln!(
+			instruction!(
 				output,
-				"\tdb {}, {condition_result}, LOW(.__else{l}), HIGH(.__else{l})",
-				env.expand("jmp_if_false")?
+				env.expand("jmp_if_false")?,
+				condition_result,
+				jump_target(&format!(".__else{l}"), options),
 			)?;
 
 			vtable.autofree(condition_result);
 
 			vtable.push_scope();
 			for i in contents {
-				compile_statement(i, env, type_table, label_index, vtable, str_table, output)?;
+				compile_statement(i, ctx, label_index, vtable, function, output)?;
 			}
 			vtable.pop_scope();
 
 			if let Some(..) = else_contents {
-				writeln!(
+				instruction!(
 					output,
-					"\tdb {}, LOW(.__end{l}), HIGH(.__end{l})",
-					env.expand("jmp")?
+					env.expand("jmp")?,
+					jump_target(&format!(".__end{l}"), options),
 				)?;
 			}
 
-			writeln!(output, ".__else{l}")?;
+			output.label(&format!(".__else{l}"))?;
 
 			if let Some(else_statements) = else_contents {
 				vtable.push_scope();
 				for i in else_statements {
-					compile_statement(i, env, type_table, label_index, vtable, str_table, output)?;
+					compile_statement(i, ctx, label_index, vtable, function, output)?;
 				}
 				vtable.pop_scope();
 			}
 
-			writeln!(output, ".__end{l}")?;
+			output.label(&format!(".__end{l}"))?;
 		}
 		StatementType::While(condition, contents) => {
 			let l = *label_index;
 			*label_index += 1;
 
 			// Jump to the condition first.
-			writeln!(
+			instruction!(
 				output,
-				"\tdb {}, LOW(.__end{l}), HIGH(.__end{l})",
-				env.expand("jmp")?
+				env.expand("jmp")?,
+				jump_target(&format!(".__end{l}"), options),
 			)?;
 
-			writeln!(output, ".__while{l}")?;
+			output.label(&format!(".__while{l}"))?;
 
 			vtable.push_scope();
 			for i in contents {
-				compile_statement(i, env, type_table, label_index, vtable, str_table, output)?;
+				compile_statement(i, ctx, label_index, vtable, function, output)?;
 			}
 			vtable.pop_scope();
 			
-			writeln!(output, ".__end{l}")?;
+			output.label(&format!(".__end{l}"))?;
 
-			let condition_result = compile_expression(condition, env, type_table, vtable, str_table, output)?
-				.ok_or(statement_error(String::from("Expression has no return value")))?;
+			let condition_result = compile_value(condition, ctx, vtable, function, output)
+				.map_err(in_statement)?;
 
-			writeln!(
+			instruction!(
 				output,
-				"\tdb {}, {condition_result}, LOW(.__while{l}), HIGH(.__while{l})",
-				env.expand("jmp_if_true")?
+				env.expand("jmp_if_true")?,
+				condition_result,
+				jump_target(&format!(".__while{l}"), options),
 			)?;
 
 			vtable.autofree(condition_result);
@@ -1150,23 +2889,24 @@ fn compile_statement<W: Write>(
 			let l = *label_index;
 			*label_index += 1;
 
-			writeln!(output, ".__while{l}")?;
+			output.label(&format!(".__while{l}"))?;
 
 			vtable.push_scope();
 			for i in contents {
-				compile_statement(i, env, type_table, label_index, vtable, str_table, output)?;
+				compile_statement(i, ctx, label_index, vtable, function, output)?;
 			}
 			vtable.pop_scope();
 			
-			writeln!(output, ".__end{l}")?;
+			output.label(&format!(".__end{l}"))?;
 
-			let condition_result = compile_expression(condition, env, type_table, vtable, str_table, output)?
-				.ok_or(statement_error(String::from("Expression has no return value")))?;
+			let condition_result = compile_value(condition, ctx, vtable, function, output)
+				.map_err(in_statement)?;
 
-			writeln!(
+			instruction!(
 				output,
-				"\tdb {}, {condition_result}, LOW(.__while{l}), HIGH(.__while{l})",
-				env.expand("jmp_if_true")?
+				env.expand("jmp_if_true")?,
+				condition_result,
+				jump_target(&format!(".__while{l}"), options),
 			)?;
 
 			vtable.autofree(condition_result);
@@ -1175,96 +2915,172 @@ fn compile_statement<W: Write>(
 			let l = *label_index;
 			*label_index += 1;
 
+			// Variables declared by the prologue only exist within the loop.
+			vtable.push_scope();
+
 			// Execute prologue
-			compile_statement(*prologue, env, type_table, label_index, vtable, str_table, output)?;
+			compile_statement(*prologue, ctx, label_index, vtable, function, output)?;
 
 			// Jump to the condition first.
-			writeln!(
+			instruction!(
 				output,
-				"\tdb {}, LOW(.__end{l}), HIGH(.__end{l})",
-				env.expand("jmp")?
+				env.expand("jmp")?,
+				jump_target(&format!(".__end{l}"), options),
 			)?;
 
-			writeln!(output, ".__for{l}")?;
+			output.label(&format!(".__for{l}"))?;
 
 			vtable.push_scope();
 			for i in contents {
-				compile_statement(i, env, type_table, label_index, vtable, str_table, output)?;
+				compile_statement(i, ctx, label_index, vtable, function, output)?;
 			}
 			vtable.pop_scope();
 
 			// Execute epliogue before checking condition
-			compile_statement(*epilogue, env, type_table, label_index, vtable, str_table, output)?;
+			compile_statement(*epilogue, ctx, label_index, vtable, function, output)?;
 			
-			writeln!(output, ".__end{l}")?;
+			output.label(&format!(".__end{l}"))?;
 
-			let condition_result = compile_expression(condition, env, type_table, vtable, str_table, output)?
-				.ok_or(statement_error(String::from("Expression has no return value")))?;
+			let condition_result = compile_value(condition, ctx, vtable, function, output)
+				.map_err(in_statement)?;
 
-			writeln!(
+			instruction!(
 				output,
-				"\tdb {}, {condition_result}, LOW(.__for{l}), HIGH(.__for{l})",
-				env.expand("jmp_if_true")?
+				env.expand("jmp_if_true")?,
+				condition_result,
+				jump_target(&format!(".__for{l}"), options),
 			)?;
 
 			vtable.autofree(condition_result);
+			vtable.pop_scope();
 		}
-		StatementType::Repeat(repeat_count, contents) => {
+		StatementType::Repeat(mut repeat_count, contents, attributes) => {
+			// How many copies of the contents each iteration runs.
+			let mut copies = 1;
+
+			// A short constant count isn't worth a counter; the contents are simply repeated.
+			if let (true, Ok(count @ 0..=3)) = (attributes.is_empty(), repeat_count.eval_const_defined(&options.defines)) {
+				for _ in 0..count {
+					vtable.push_scope();
+					for i in contents.clone() {
+						compile_statement(i, ctx, label_index, vtable, function, output)?;
+					}
+					vtable.pop_scope();
+				}
+				return Ok(());
+			}
+
+			for i in &attributes {
+				let attribute_error = |msg: String| CompilerError::spanned(i.start, i.end, msg);
+
+				match i.name.as_str() {
+					"unroll" => {
+						let count = repeat_count.eval_const_defined(&options.defines)
+							.map_err(|_| attribute_error(String::from("@unroll requires a constant repeat count")))?;
+						if count < 0 {
+							return Err(statement_error(format!("Invalid repeat count {count}")));
+						}
+						// Without a factor, the loop is unrolled entirely.
+						let factor = match i.args.as_slice() {
+							[] => count.max(1),
+							[factor] => factor.eval_const_defined(&options.defines).map_err(attribute_error)?,
+							_ => return Err(attribute_error(String::from("@unroll expects a single factor"))),
+						};
+						if factor <= 0 {
+							return Err(attribute_error(format!("Invalid unroll factor {factor}")));
+						}
+
+						// Iterations that don't fill a whole unrolled iteration are run ahead of the loop,
+						// as is a loop that would only run once.
+						let mut leading = count % factor;
+						let mut iterations = count / factor;
+						if iterations <= 1 {
+							leading += iterations * factor;
+							iterations = 0;
+						}
+
+						for _ in 0..leading {
+							vtable.push_scope();
+							for i in contents.clone() {
+								compile_statement(i, ctx, label_index, vtable, function, output)?;
+							}
+							vtable.pop_scope();
+						}
+
+						if iterations == 0 {
+							return Ok(());
+						}
+						copies = factor;
+						repeat_count = Rpn::Signed(iterations);
+					}
+					_ => return Err(attribute_error(format!("Unknown attribute @{}", i.name))),
+				}
+			}
+
 			let l = *label_index;
 			*label_index += 1;
 
 			// Execute prologue
-			let mut repeat_index = compile_expression(repeat_count, env, type_table, vtable, str_table, output)?
-				.ok_or(statement_error(String::from("Expression has no return value")))?;
+			let mut repeat_index = compile_value(repeat_count, ctx, vtable, function, output)
+				.map_err(in_statement)?;
 
 			if vtable.name_of(repeat_index).is_some() {
 				let dest_type = vtable.type_of(repeat_index);
 				let unique_index = vtable.alloc(Type::Primative(dest_type))?;
-				writeln!(output, "\tdb {}, {unique_index}, {repeat_index}", env.expand(&format!("mov_{dest_type}"))?)?;
+				instruction!(output, env.expand(&format!("mov_{dest_type}"))?, unique_index, repeat_index)?;
 				repeat_index = unique_index;
 			}
 
-			writeln!(output, ".__repeat{l}")?;
+			output.label(&format!(".__repeat{l}"))?;
 
-			vtable.push_scope();
-			for i in contents {
-				compile_statement(i, env, type_table, label_index, vtable, str_table, output)?;
+			for _ in 0..copies {
+				vtable.push_scope();
+				for i in contents.clone() {
+					compile_statement(i, ctx, label_index, vtable, function, output)?;
+				}
+				vtable.pop_scope();
 			}
-			vtable.pop_scope();
 
 			// Execute epilogue before checking condition
-			let scratch = vtable.alloc(Type::Primative(Primative { signed: false, size: 1 }))?;
+			let scratch = vtable.alloc(Type::Primative(Primative { signed: false, size: 1, fixed: false }))?;
 
-			writeln!(
+			instruction!(
 				output,
-				"\tdb {}, {scratch}, $1",
-				env.expand("put_u8")?
+				env.expand("put_u8")?,
+				scratch,
+				"$1",
 			)?;
 
-			writeln!(
+			instruction!(
 				output,
-				"\tdb {}, {repeat_index}, {scratch}, {repeat_index}",
-				env.expand("sub_u8")?
+				env.expand("sub_u8")?,
+				repeat_index,
+				scratch,
+				repeat_index,
 			)?;
 			
-			writeln!(output, ".__end{l}")?;
+			output.label(&format!(".__end{l}"))?;
 
-			writeln!(
+			instruction!(
 				output,
-				"\tdb {}, {scratch}, $0",
-				env.expand("put_u8")?
+				env.expand("put_u8")?,
+				scratch,
+				"$0",
 			)?;
 
-			writeln!(
+			instruction!(
 				output,
-				"\tdb {}, {repeat_index}, {scratch}, {scratch}",
-				env.expand("equ_u8")?
+				env.expand("equ_u8")?,
+				repeat_index,
+				scratch,
+				scratch,
 			)?;
 
-			writeln!(
+			instruction!(
 				output,
-				"\tdb {}, {scratch}, LOW(.__repeat{l}), HIGH(.__repeat{l})",
-				env.expand("jmp_if_false")?
+				env.expand("jmp_if_false")?,
+				scratch,
+				jump_target(&format!(".__repeat{l}"), options),
 			)?;
 
 			vtable.autofree(scratch);
@@ -1274,134 +3090,541 @@ fn compile_statement<W: Write>(
 			let l = *label_index;
 			*label_index += 1;
 
-			writeln!(output, ".__loop{l}")?;
+			output.label(&format!(".__loop{l}"))?;
 
 			vtable.push_scope();
 			for i in contents {
-				compile_statement(i, env, type_table, label_index, vtable, str_table, output)?;
+				compile_statement(i, ctx, label_index, vtable, function, output)?;
 			}
 			vtable.pop_scope();
 
-			writeln!(
+			instruction!(
 				output,
-				"\tdb {}, LOW(.__loop{l}), HIGH(.__loop{l})",
-				env.expand("jmp")?
+				env.expand("jmp")?,
+				jump_target(&format!(".__loop{l}"), options),
 			)?;
 			
-			writeln!(output, ".__end{l}")?;
+			output.label(&format!(".__end{l}"))?;
+		}
+		StatementType::Switch(value, cases, default) => {
+			let l = *label_index;
+			*label_index += 1;
+
+			let value = compile_value(value, ctx, vtable, function, output)
+				.map_err(in_statement)?;
+
+			if vtable.type_of(value).size != 1 {
+				return Err(statement_error(String::from("Only 8-bit values can be switched on")));
+			}
+
+			// Compare the value against each case in order, jumping to the first one that matches.
+			let scratch = vtable.alloc(Type::Primative(Primative { signed: false, size: 1, fixed: false }))?;
+
+			for (i, case) in cases.iter().enumerate() {
+				for case_value in &case.values {
+					let case_value = case_value.eval_const_defined(&options.defines).map_err(statement_error)?;
+
+					instruction!(output, env.expand("put_u8")?, scratch, case_value)?;
+					instruction!(output, env.expand("equ_u8")?, value, scratch, scratch)?;
+					instruction!(
+						output,
+						env.expand("jmp_if_true")?,
+						scratch,
+						jump_target(&format!(".__case{l}_{i}"), options),
+					)?;
+				}
+			}
+
+			vtable.autofree(scratch);
+			vtable.autofree(value);
+
+			let no_match = if default.is_some() { format!(".__default{l}") } else { format!(".__end{l}") };
+			instruction!(output, env.expand("jmp")?, jump_target(&no_match, options))?;
+
+			let case_count = cases.len();
+			for (i, case) in cases.into_iter().enumerate() {
+				output.label(&format!(".__case{l}_{i}"))?;
+
+				vtable.push_scope();
+				for i in case.contents {
+					compile_statement(i, ctx, label_index, vtable, function, output)?;
+				}
+				vtable.pop_scope();
+
+				// Unless told to fall through, each case leaves the switch when it is done.
+				// The last case doesn't need to jump if nothing follows it.
+				if !case.fallthrough && (i + 1 < case_count || default.is_some()) {
+					instruction!(output, env.expand("jmp")?, jump_target(&format!(".__end{l}"), options))?;
+				}
+			}
+
+			if let Some(default) = default {
+				output.label(&format!(".__default{l}"))?;
+
+				vtable.push_scope();
+				for i in default {
+					compile_statement(i, ctx, label_index, vtable, function, output)?;
+				}
+				vtable.pop_scope();
+			}
+
+			output.label(&format!(".__end{l}"))?;
+		}
+		StatementType::StaticAssert(condition, message) => {
+			check_static_assert(&condition, message, type_table, options).map_err(statement_error)?;
 		}
-		_ => return Err(CompilerError {
-			start: Some(statement.start),
-			end: Some(statement.end),
-			msg: String::from("Statement not allowed in function"),
-		} ),
+		// Data is compiled once per function by `compile_function_data`, even within a repeat whose contents are duplicated.
+		StatementType::Data(..) => {}
+		_ => return Err(CompilerError::spanned(statement.start, statement.end, String::from("Statement not allowed in function"))),
 	};
 
 	Ok(())
 }
 
-fn compile_function<W: Write>(
+fn compile_function<B: EmitBackend>(
 	name: &str,
 	func: types::Function,
-	environment_table: &EnvironmentTable,
-	type_table: &TypeTable,
-	output: &mut W,
-	options: &CompilerOptions,
-) -> Result<(), CompilerError> {
-	let env = match environment_table.get(&func.environment) {
+	state: &mut CompilerState,
+	output: &mut B,
+	continue_section: Option<&str>,
+	source: &SourceLines,
+	warnings: &mut Vec<CompilerError>,
+) -> Result<bool, CompilerError> {
+	let options = state.options;
+	let CompilerState { environment_table, type_table, function_table, summary, .. } = state;
+	let environment = &function_table[name].environment;
+	let env = match environment_table.get(environment) {
 		Some(env) => env,
-		None => return Err(CompilerError {
-			start: Some(func.start),
-			end: Some(func.end),
-			msg: format!("Environment {} does not exist", func.environment),			
-		} ),
+		None => return Err(CompilerError::spanned(func.start, func.end, format!("Environment {environment} does not exist"))),
 	};
+	let mut falls_through = options.no_terminator;
+
+	for i in &func.attributes {
+		let attribute_error = |msg: String| CompilerError::spanned(i.start, i.end, msg);
+
+		match i.name.as_str() {
+			// Execution continues into whatever follows this function in ROM.
+			"falls_through" => {
+				if !i.args.is_empty() {
+					return Err(attribute_error(String::from("@falls_through does not take arguments")));
+				}
+				falls_through = true;
+			}
+			// Validated when the function is registered.
+			"bank" => {}
+			_ => return Err(attribute_error(format!("Unknown attribute @{}", i.name))),
+		}
+	}
+
 	let mut vtable = VariableTable::new();
-	let mut str_table = Vec::<String>::new();
+	for reservation in &env.reservations {
+		vtable.reserve(reservation);
+	}
+	let mut function = FunctionState {
+		name: String::from(name),
+		strings: Vec::new(),
+		data: Vec::new(),
+		expression_label: 0,
+		warnings: Vec::new(),
+		source: options.line_directives.then(|| source.clone()),
+		// Unless the environment chooses, constants are bytes, or words if the function has one which needs it.
+		// Without 16-bit puts, each constant which doesn't fit in a byte is widened on its own instead.
+		int_type: match env.default_int {
+			Some(t) => t,
+			None if env.lookup("put_u16").is_ok() && needs_wide_integers(&func.contents, &options.defines) => {
+				Primative { signed: false, size: 2, fixed: false }
+			}
+			None => Primative::default_integer(),
+		},
+	};
 	let mut label_index = 0;
 
-	writeln!(output, "\nsection \"{name} evscript fn\", romx\n{name}::")?;
+	// A function following one that falls through must be placed directly after it.
+	let bank = function_table.get(name).and_then(|info| info.bank);
+	if let Some(previous) = continue_section {
+		let previous_bank = function_table.get(previous).and_then(|info| info.bank);
+		if let Some(bank) = bank.filter(|&bank| previous_bank != Some(bank)) {
+			return Err(CompilerError::spanned(func.start, func.end, format!("{name} is placed in bank {bank}, but {previous} falls through into it from another section")));
+		}
+		output.directive("")?;
+	} else if let Some(bank) = bank {
+		output.directive("")?;
+		output.directive(&format!("section \"{name} evscript fn\", romx, bank[{bank}]"))?;
+	} else {
+		output.directive("")?;
+		output.directive(&format!("section \"{name} evscript fn\", romx"))?;
+	}
+	output.label(&format!("{name}::"))?;
+
+	for (start, end, variable) in DeadStores::find(&func.contents) {
+		function.warnings.push(CompilerError::spanned(start, end, format!("the value stored in {variable} is overwritten before it is read")));
+	}
+
+	// The body is buffered so that its size can be measured before it is written out.
+	let mut body = Vec::<Op>::new();
+
+	// A final yield and the terminator after it may be a single opcode, if the environment has one.
+	let mut contents = func.contents;
+	let yield_return = !falls_through
+		&& env.definitions.contains_key("yld_ret")
+		&& matches!(
+			contents.last(),
+			Some(Statement { t: StatementType::Expression(Rpn::Call(name, args, ..)), .. }) if name == "yld" && args.is_empty()
+		);
+	if let (true, Some(Statement { t: StatementType::Expression(Rpn::Call(name, ..)), .. })) = (yield_return, contents.last_mut()) {
+		// It is compiled like any other statement, so that it is checked and annotated like the yield it replaces.
+		*name = String::from("yld_ret");
+	}
 
-	for i in func.contents {
-		compile_statement(i, env, type_table, &mut label_index, &mut vtable, &mut str_table, output)?;
+	compile_function_data(&contents, options, &mut function)?;
+	let ctx = &Context { env, type_table, function_table, options };
+	for i in contents {
+		compile_statement(i, ctx, &mut label_index, &mut vtable, &mut function, &mut body)?;
 	}
 
-	writeln!(output, "\tdb 0")?;
+	if falls_through {
+		if !function.strings.is_empty() || !function.data.is_empty() {
+			// Strings and data are placed after the function body, where they would be executed.
+			return Err(CompilerError::spanned(func.start, func.end, format!("{name} falls through and may not contain strings or data")));
+		}
+	} else if !yield_return {
+		match &options.terminator {
+			Some(terminator) => instruction!(body, env.expand(terminator)?)?,
+			None => {
+				instruction!(body, "0")?;
+				// The terminator is whichever definition has bytecode 0, normally `ret`.
+				if let Some(Some(terminator)) = env.bytecode_names().first() {
+					env.used.borrow_mut().insert(String::from(*terminator));
+				}
+			}
+		}
+	}
 
 	let mut i = 0;
-	while i < str_table.len() {
-		writeln!(output, ".__string{i} db \"{}\", 0", str_table[i])?;
+	while i < function.strings.len() {
+		body.label(&format!(".__string{i}"))?;
+		let mut values = function.strings[i].clone();
+		values.push(String::from("0"));
+		body.data(1, &strs(&values))?;
 		i += 1;
 	}
+	body.append(&mut function.data);
+
+	let size = body.iter().filter_map(Op::size).sum::<usize>();
+	let macros = body.iter().filter(|op| op.size().is_none()).count();
+	if let Some(limit) = options.max_function_size {
+		if size > limit {
+			return Err(CompilerError::spanned(func.start, func.end, format!("{name} is {size} bytes long, more than the limit of {limit}")));
+		}
+	}
 
-	if options.report_usage {
-		println!("({name}) Peak usage: {}", vtable.peak_usage);
+	summary.functions += 1;
+	summary.function_bytes += size;
+	summary.macro_invocations += macros;
+	for op in &body {
+		op.emit(output)?;
 	}
+	if let (Some(limit), true) = (options.max_function_size, macros > 0) {
+		output.directive(&format!("\tassert @ - {name} <= {limit}, \"{name} is more than the limit of {limit} bytes long\""))?;
+	}
+
+	if options.pool_map && vtable.peak_usage > 0 {
+		output.directive(&format!("; {name} uses pool bytes $00-${:02X} of {}", vtable.peak_usage - 1, env.name))?;
+	}
+
+	summary.peak_usage.push((String::from(name), vtable.peak_usage));
 
-	if (vtable.peak_usage as u16) > env.pool {
-		eprintln!(
-			"WARN: {name} is using {} bytes, more than the maximum pool size for {}: {}",
+	if vtable.peak_usage > env.pool as usize {
+		function.warnings.push(CompilerError::from(format!(
+			"{name} is using {} bytes, more than the maximum pool size for {}: {}",
 			vtable.peak_usage,
 			env.name,
 			env.pool,
-		);
+		)));
 	}
 
-	Ok(())
+	warnings.append(&mut function.warnings);
+	Ok(falls_through)
+}
+
+/// An error for something defined twice, which also points at the first definition if it is in the same file.
+fn redefinition_error(
+	msg: String,
+	span: std::ops::Range<usize>,
+	file: &str,
+	previous: std::ops::Range<usize>,
+	previous_file: &str,
+) -> CompilerError {
+	if file == previous_file {
+		CompilerError {
+			start: Some(span.start),
+			end: Some(span.end),
+			previous: Some(previous),
+			file: None,
+			msg,
+		}
+	} else {
+		CompilerError::spanned(span.start, span.end, format!("{msg} in {previous_file}"))
+	}
+}
+
+/// The file and span of each label given to data outside of a function.
+type DataLabels = HashMap<String, (String, std::ops::Range<usize>)>;
+
+/// Finds and reads a file included by the file at `including`, returning the path it was found at and its contents.
+/// A relative path is looked for next to the including file, then in the current directory,
+/// then in each of `include_paths` in order.
+pub(crate) fn read_include(path: &str, including: &str, include_paths: &[String]) -> Result<(String, String), String> {
+	let mut candidates = Vec::<String>::new();
+	if let Some(directory) = Path::new(including).parent().filter(|directory| !directory.as_os_str().is_empty()) {
+		if Path::new(path).is_relative() {
+			candidates.push(directory.join(path).to_string_lossy().into_owned());
+		}
+	}
+	candidates.push(String::from(path));
+	if Path::new(path).is_relative() {
+		candidates.extend(include_paths.iter().map(|directory| Path::new(directory).join(path).to_string_lossy().into_owned()));
+	}
+	candidates.dedup();
+
+	for candidate in &candidates {
+		match read_to_string(candidate) {
+			Ok(input) => return Ok((candidate.clone(), input)),
+			Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+			Err(err) => return Err(format!("{candidate}: {err}")),
+		}
+	}
+
+	Err(match candidates.len() {
+		1 => format!("{path}: file not found"),
+		_ => format!("{path}: file not found; tried {}", candidates.join(", ")),
+	})
+}
+
+/// The files included so far.
+struct Includes {
+	/// Every file which was included, so that none is included twice.
+	files: HashSet<PathBuf>,
+	/// The path of each file currently being compiled, starting with the root file,
+	/// along with its canonical path if it could be found.
+	stack: Vec<(String, Option<PathBuf>)>,
+}
+
+impl Includes {
+	/// Describes the files being compiled and the one they include, such as `a.evs -> b.evs -> a.evs`.
+	fn chain(&self, path: &str) -> String {
+		let mut chain = self.stack.iter().map(|(path, _)| path.as_str()).collect::<Vec<&str>>();
+		chain.push(path);
+		chain.join(" -> ")
+	}
+}
+
+/// Everything a compilation builds up, across the files it includes.
+struct CompilerState<'a> {
+	options: &'a CompilerOptions,
+	environment_table: EnvironmentTable,
+	type_table: TypeTable,
+	function_table: FunctionTable,
+	data_labels: DataLabels,
+	includes: Includes,
+	summary: CompilerSummary,
+	/// The environments' bytecode equates, and the size of each type and offset of each struct member,
+	/// if they are written to a header rather than with the rest of the output.
+	header: Option<Vec<Op>>,
 }
 
-fn compile_ast<W: Write>(
+fn compile_ast<B: EmitBackend>(
 	ast: Vec<types::Root>,
-	environment_table: &mut EnvironmentTable,
-	type_table: &mut TypeTable,
-	output: &mut W,
-	options: &CompilerOptions,
+	state: &mut CompilerState,
+	output: &mut B,
+	source: &SourceLines,
+	warnings: &mut Vec<CompilerError>,
 ) -> Result<(), CompilerError> {
+	let options = state.options;
+	let file = state.includes.stack.last().map(|(path, _)| path.clone()).unwrap_or_default();
+
+	// Register every function up front so that they may call each other regardless of order.
+	for i in &ast {
+		if let types::Root::Function(name, func) = i {
+			if let Some(previous) = state.function_table.get(name) {
+				return Err(redefinition_error(
+					format!("Function {name} is already defined"),
+					func.start..func.end,
+					&file,
+					previous.start..previous.end,
+					&previous.file,
+				));
+			}
+			if let Some((previous_file, previous)) = state.data_labels.get(name) {
+				return Err(redefinition_error(
+					format!("{name} is already defined as a data label"),
+					func.start..func.end,
+					&file,
+					previous.clone(),
+					previous_file,
+				));
+			}
+			state.function_table.insert(name.clone(), FunctionInfo::new(func, &file, options)?);
+		}
+	}
+
+	// The function falling through into the next one, if any.
+	let mut falls_through: Option<String> = None;
+
 	for i in ast {
 		match i {
 			types::Root::Environment(name, env) => {
-				let new_env = compile_environment(&name, env, &environment_table, output)?;
-				environment_table.insert(name, new_env);
+				if let Some(previous) = state.environment_table.get(&name) {
+					return Err(redefinition_error(
+						format!("Environment {name} is already defined"),
+						env.start..env.end,
+						&file,
+						previous.start..previous.end,
+						&previous.file,
+					));
+				}
+				let mut header = state.header.take();
+				let new_env = match (&mut header, options.no_header) {
+					(Some(header), _) => compile_environment(&name, env, state, &file, header, warnings),
+					(None, true) => compile_environment(&name, env, state, &file, &mut RgbdsBackend::new(io::sink()), warnings),
+					(None, false) => compile_environment(&name, env, state, &file, output, warnings),
+				};
+				state.header = header;
+				let new_env = new_env?;
+				if options.pool_map {
+					write_pool_map(&new_env, output)?;
+				}
+				state.environment_table.insert(name, new_env);
 			}
 			types::Root::Function(name, func) => {
-				compile_function(&name, func, &environment_table, &type_table, output, &options)?;
+				falls_through = compile_function(&name, func, state, output, falls_through.as_deref(), source, warnings)?
+					.then(|| name.clone());
 			}
 			types::Root::Assembly(contents) => {
-				writeln!(output, "{}", contents)?;
-			}
-			types::Root::Include(path) => {
-				let input = &match read_to_string(&path) {
-					Ok(input) => input,
-					Err(err) => {
-						eprintln!("{path}: {err}");
-						exit(1);
+				output.directive(&contents)?;
+			}
+			types::Root::Include { path, start, end } => {
+				let including = &state.includes.stack.last().expect("the root file is always on the stack").0;
+				let (path, input) = read_include(&path, including, &options.include_paths).map_err(|msg| CompilerError::spanned(start, end, msg))?;
+				let input = &input;
+
+				let canonical_path = canonicalize(&path).ok();
+				if let Some(canonical_path) = &canonical_path {
+					// A file which state.includes itself, directly or not, would never finish compiling.
+					if state.includes.stack.iter().any(|(_, i)| i.as_ref() == Some(canonical_path)) {
+						return Err(CompilerError::spanned(start, end, format!("Include cycle: {}", state.includes.chain(&path))));
 					}
-				};
-
-				let ast = match crate::parse(input) {
-					Ok(ast) => ast,
-					Err(err) => {
-						eprintln!("{path}: {err}");
-						exit(1);
+					// Each file is only included once, however its path is spelled.
+					if !state.includes.files.insert(canonical_path.clone()) {
+						continue;
 					}
-				};
+				}
 
-				if let Err(err) = compile_ast(ast, environment_table, type_table, output, options) {
-					eprintln!("{path}: {err}");
-					exit(1);
+				state.summary.included_files.push(path.clone());
+
+				if state.includes.stack.len() > options.include_depth_limit {
+					return Err(CompilerError::spanned(start, end, format!(
+						"Includes are nested more than {} levels deep: {}",
+						options.include_depth_limit,
+						state.includes.chain(&path),
+					)));
 				}
+
+				// Diagnostics from included files keep their path, since their spans belong to another file.
+				// Only the first syntax error is reported, as the others are often caused by it.
+				let ast = crate::parse(input).map_err(|errors| {
+					CompilerError::from(errors.into_iter().next().expect("parsing only fails with an error")).in_file(&path)
+				})?;
+
+				let mut include_warnings = Vec::new();
+				state.includes.stack.push((path.clone(), canonical_path));
+				let result = compile_ast(ast, state, output, &SourceLines::new(&path, input), &mut include_warnings);
+				state.includes.stack.pop();
+				warnings.extend(include_warnings.into_iter().map(|warning| warning.in_file(&path)));
+				result.map_err(|err| err.in_file(&path))?;
 			}
-			types::Root::Typedef { name, t } => {
-				type_table.table.insert(name, type_table.lookup_type(&t)?);
+			types::Root::Typedef { name, t, start, end } => {
+				let typedef_error = |msg: String| CompilerError::spanned(start, end, msg);
+
+				state.type_table.check_redefinition(&name, start..end, &file)?;
+				// The target is resolved now, so that a typedef is never an alias to chase later,
+				// and can only refer to types defined before it, so that it can't form a cycle.
+				if t == name {
+					return Err(typedef_error(format!("{name} cannot be defined as itself")));
+				}
+				let t = state.type_table.lookup_type(&t).map_err(typedef_error)?;
+				if let Some(header) = &mut state.header {
+					header.directive(&format!("def sizeof@{name} equ {}", t.size()))?;
+				}
+				state.type_table.define(name, t, start..end, &file);
 			}
-			types::Root::Struct { name, contents } => {
+			types::Root::Struct { name, contents, start, end } => {
+				state.type_table.check_redefinition(&name, start..end, &file)?;
+				// A variable without a size would share its slot with the next one.
+				if contents.is_empty() {
+					return Err(CompilerError::spanned(start, end, format!("{name} has no members")));
+				}
+
 				let mut struct_members = Vec::<(String, Type)>::new();
 
 				for i in contents {
-					struct_members.push((i.name, type_table.lookup_type(&i.t)?));
+					let member_error = |msg: String| CompilerError::spanned(i.start, i.end, msg);
+
+					if struct_members.iter().any(|(member, _)| *member == i.name) {
+						return Err(member_error(format!("{name} already has a member named {}", i.name)));
+					}
+					// Members may only use types defined before the struct, so this is the only way it could recurse.
+					if i.t == name {
+						return Err(member_error(format!("{name} cannot contain itself; its size would be infinite")));
+					}
+					let t = state.type_table.lookup_type(&i.t).map_err(member_error)?;
+					struct_members.push((i.name, t));
+				}
+
+				if let Some(header) = &mut state.header {
+					let mut offset = 0;
+					for (member, t) in &struct_members {
+						header.directive(&format!("def {name}@{member} equ {offset}"))?;
+						offset += t.size() as usize;
+					}
+					header.directive(&format!("def sizeof@{name} equ {offset}"))?;
 				}
 
-				type_table.table.insert(name, Type::Struct(struct_members));
+				state.type_table.define(name, Type::Struct(struct_members), start..end, &file);
+			}
+			types::Root::StaticAssert { condition, message, start, end } => {
+				check_static_assert(&condition, message, &state.type_table, options).map_err(|msg| CompilerError::spanned(start, end, msg))?;
+			}
+			types::Root::Data { label, size, values, start, end } => {
+				let label = label.ok_or(CompilerError::spanned(start, end, String::from("Data outside of a function must be labeled")))?;
+				// Data labels are global, like function names.
+				if let Some(previous) = state.function_table.get(&label) {
+					return Err(redefinition_error(
+						format!("{label} is already defined as a function"),
+						start..end,
+						&file,
+						previous.start..previous.end,
+						&previous.file,
+					));
+				}
+				if let Some((previous_file, previous)) = state.data_labels.get(&label) {
+					return Err(redefinition_error(
+						format!("Data label {label} is already defined"),
+						start..end,
+						&file,
+						previous.clone(),
+						previous_file,
+					));
+				}
+				state.data_labels.insert(label.clone(), (file.clone(), start..end));
+				let operands = compile_data(size, &values, options).map_err(|err| CompilerError::spanned(start, end, err.msg))?;
+
+				output.directive("")?;
+				output.directive(&format!("section \"{label} evscript data\", romx"))?;
+				output.label(&format!("{label}::"))?;
+				data_op(size, &operands, options).emit(output)?;
+				state.summary.data_sections += 1;
+				state.summary.data_bytes += operands.len() * size as usize;
 			}
 		}
 	}
@@ -1409,19 +3632,215 @@ fn compile_ast<W: Write>(
 	Ok(())
 }
 
+/// Compiles `ast`, which was parsed from `input`, the contents of the file at `path`.
 pub fn compile<W: Write>(
 	ast: Vec<types::Root>,
 	path: &str,
+	input: &str,
 	output: &mut W,
 	options: CompilerOptions,
-) -> Result<(), CompilerError> {
-	let mut environment_table = EnvironmentTable::new();
+	warnings: &mut Vec<CompilerError>,
+) -> Result<CompilerSummary, CompilerError> {
+	compile_with(ast, path, input, &mut RgbdsBackend::new(output), options, warnings)
+}
+
+/// Compiles `ast` like [`compile`], handing the output to `backend`.
+pub fn compile_with<B: EmitBackend>(
+	ast: Vec<types::Root>,
+	path: &str,
+	input: &str,
+	output: &mut B,
+	options: CompilerOptions,
+	warnings: &mut Vec<CompilerError>,
+) -> Result<CompilerSummary, CompilerError> {
+	compile_root(ast, path, input, output, None, options, warnings)
+}
+
+/// Compiles `ast` like [`compile_with`], handing the environments' bytecode equates, and the size of
+/// each type and offset of each struct member, to `header` rather than `output`.
+pub fn compile_with_header<B: EmitBackend, H: EmitBackend>(
+	ast: Vec<types::Root>,
+	path: &str,
+	input: &str,
+	output: &mut B,
+	header: &mut H,
+	options: CompilerOptions,
+	warnings: &mut Vec<CompilerError>,
+) -> Result<CompilerSummary, CompilerError> {
+	let mut ops = Vec::new();
+	let summary = compile_root(ast, path, input, output, Some(&mut ops), options, warnings)?;
+	for op in &ops {
+		op.emit(header)?;
+	}
+	Ok(summary)
+}
+
+fn compile_root<B: EmitBackend>(
+	ast: Vec<types::Root>,
+	path: &str,
+	input: &str,
+	output: &mut B,
+	header: Option<&mut Vec<Op>>,
+	options: CompilerOptions,
+	warnings: &mut Vec<CompilerError>,
+) -> Result<CompilerSummary, CompilerError> {
+	let type_table = TypeTable { table: HashMap::<String, Type>::from([
+		(String::from("u8"), Type::Primative(Primative { signed: false, size: 1, fixed: false } )),
+		(String::from("u16"), Type::Primative(Primative { signed: false, size: 2, fixed: false } )),
+		(String::from("i8"), Type::Primative(Primative { signed: true, size: 1, fixed: false } )),
+		(String::from("i16"), Type::Primative(Primative { signed: true, size: 2, fixed: false } )),
+		(String::from("q8.8"), Type::Primative(Primative::fixed_point())),
+	]), definitions: HashMap::new() };
+
+	output.directive(&format!("def __EVSCRIPT_FILE__ equs {path:?}"))?;
+	// The root file counts as included, so that including it again is reported as a cycle.
+	let canonical_path = canonicalize(path).ok();
+	let mut includes = Includes {
+		files: HashSet::new(),
+		stack: vec![(String::from(path), canonical_path.clone())],
+	};
+	if let Some(canonical_path) = canonical_path {
+		includes.files.insert(canonical_path);
+	}
+
+	let mut state = CompilerState {
+		options: &options,
+		environment_table: EnvironmentTable::new(),
+		type_table,
+		function_table: FunctionTable::new(),
+		data_labels: DataLabels::new(),
+		includes,
+		summary: CompilerSummary::default(),
+		header: header.is_some().then(Vec::new),
+	};
+	compile_ast(ast, &mut state, output, &SourceLines::new(path, input), warnings)?;
+	let CompilerState { environment_table, mut summary, header: header_ops, .. } = state;
+	if let (Some(header), Some(mut header_ops)) = (header, header_ops) {
+		header.append(&mut header_ops);
+	}
+
+	for (name, required) in &options.required_versions {
+		let env = environment_table.get(name)
+			.ok_or(format!("Environment {name} does not exist"))?;
+		match env.version {
+			Some(version) if version == *required => {}
+			Some(version) => return Err(CompilerError::from(format!(
+				"Environment {name} is version {version}, but version {required} is required"
+			))),
+			None => return Err(CompilerError::from(format!(
+				"Environment {name} has no version, but version {required} is required"
+			))),
+		}
+	}
+
+	if options.report_unused {
+		summary.unused_definitions = unused_definitions(&environment_table);
+	}
+
+	if options.check_macros == Some(MacroCheck::Warn) {
+		let mut targets = environment_table.values()
+			.flat_map(|env| env.used.borrow().iter()
+				.filter_map(|name| match env.definitions.get(name) {
+					Some(types::Definition::Macro(def)) => Some(def.target.clone()),
+					_ => None,
+				})
+				.collect::<Vec<String>>())
+			.collect::<Vec<String>>();
+		targets.sort();
+		targets.dedup();
+
+		if !targets.is_empty() {
+			warnings.push(CompilerError::from(format!(
+				"These macros must be defined by the assembler: {}", targets.join(", ")
+			)));
+		}
+	}
+
+	if let Some(name) = &options.dispatch_environment {
+		let env = environment_table.get(name)
+			.ok_or(format!("Environment {name} does not exist"))?;
+		compile_dispatch_table(env, output)?;
+	}
+
+	Ok(summary)
+}
+
+/// The outcome of a compilation driven by a [`Compiler`].
+pub struct Compilation {
+	/// The summary of what was written, or every error which stopped the compilation.
+	pub result: Result<CompilerSummary, Vec<CompilerError>>,
+	pub warnings: Vec<CompilerError>,
+}
+
+/// Drives a whole compilation, from reading the source to writing the assembly,
+/// returning diagnostics instead of printing them.
+pub struct Compiler {
+	path: String,
+	input: Option<String>,
+	options: CompilerOptions,
+}
+
+impl Compiler {
+	/// Compiles the file at `path`. Includes are still read from the filesystem.
+	pub fn new(path: &str) -> Compiler {
+		Compiler {
+			path: String::from(path),
+			input: None,
+			options: CompilerOptions::new(),
+		}
+	}
+
+	pub fn options(mut self, options: CompilerOptions) -> Compiler {
+		self.options = options;
+		self
+	}
+
+	/// Compiles `input` instead of reading the file's contents.
+	pub fn source(mut self, input: &str) -> Compiler {
+		self.input = Some(String::from(input));
+		self
+	}
+
+	/// Compiles whatever is read from `reader` instead of reading the file's contents.
+	pub fn reader(mut self, mut reader: impl std::io::Read) -> std::io::Result<Compiler> {
+		let mut input = String::new();
+		reader.read_to_string(&mut input)?;
+		self.input = Some(input);
+		Ok(self)
+	}
 
-	let mut type_table = TypeTable { table: HashMap::<String, Type>::from([
-		(String::from("u8"), Type::Primative(Primative { signed: false, size: 1 } )),
-		(String::from("u16"), Type::Primative(Primative { signed: false, size: 2 } )),
-	]) };
+	pub fn compile_to<W: Write>(self, output: &mut W) -> Compilation {
+		self.compile_with(&mut RgbdsBackend::new(output))
+	}
+
+	/// Hands the output to a backend, piece by piece, rather than writing RGBDS assembly.
+	pub fn compile_with<B: EmitBackend>(self, backend: &mut B) -> Compilation {
+		let mut warnings = Vec::new();
+
+		let input = match self.input {
+			Some(input) => input,
+			None => match read_to_string(&self.path) {
+				Ok(input) => input,
+				Err(err) => return Compilation {
+					result: Err(vec![CompilerError::from(format!("{}: {err}", self.path))]),
+					warnings,
+				},
+			},
+		};
 
-	writeln!(output, "def __EVSCRIPT_FILE__ equs {path:?}")?;
-	compile_ast(ast, &mut environment_table, &mut type_table, output, &options)
+		let result = match crate::parse(&input) {
+			Ok(ast) => compile_with(ast, &self.path, &input, backend, self.options, &mut warnings).map_err(|err| vec![err]),
+			Err(errors) => Err(errors.into_iter().map(CompilerError::from).collect()),
+		};
+
+		Compilation { result, warnings }
+	}
+
+	/// Returns the assembly, or None if compilation failed, along with the diagnostics and summary.
+	pub fn compile_to_string(self) -> (Option<String>, Compilation) {
+		let mut output = Vec::new();
+		let compilation = self.compile_to(&mut output);
+		let output = compilation.result.is_ok().then(|| String::from_utf8_lossy(&output).into_owned());
+		(output, compilation)
+	}
 }
@@ -1,10 +1,195 @@
 pub mod types;
 pub mod compiler;
+pub mod backend;
 
 pub use compiler::compile;
+pub use compiler::Compiler;
 use lalrpop_util::lalrpop_mod;
-lalrpop_mod!(pub parser);
+lalrpop_mod!(
+	// The recovered error list is part of the generated parser's signature.
+	#[allow(clippy::ptr_arg)]
+	pub parser
+);
 
-pub fn parse(input: &str) -> Result<std::vec::Vec<crate::types::Root>, lalrpop_util::ParseError<usize, lalrpop_util::lexer::Token<'_>, &'static str>> {
-	parser::FileParser::new().parse(input)
+pub type ParseError<'input> = lalrpop_util::ParseError<usize, lalrpop_util::lexer::Token<'input>, &'static str>;
+
+/// If a string, character or `#asm` block starts at `i`, returns the offset just past its end.
+fn skip_literal(input: &str, i: usize) -> Option<usize> {
+	let bytes = input.as_bytes();
+	match &bytes[i..] {
+		[quote @ (b'"' | b'\''), ..] => {
+			let mut i = i + 1;
+			while i < bytes.len() && bytes[i] != *quote && !(*quote == b'\'' && bytes[i] == b'\n') {
+				i += if bytes[i] == b'\\' { 2 } else { 1 };
+			}
+			Some(i + 1)
+		}
+		[b'#', b'a', b's', b'm', ..] => Some(input[i..].find("#end").map_or(bytes.len(), |end| i + end + 4)),
+		_ => None,
+	}
+}
+
+/// Replaces each comment with spaces, keeping its line breaks, so that every token stays at the same offset.
+/// Block comments may be nested; strings, characters and `#asm` blocks are left alone.
+fn blank_comments(input: &str) -> Result<std::string::String, ParseError<'static>> {
+	let bytes = input.as_bytes();
+	let mut output = bytes.to_vec();
+	let mut i = 0;
+
+	while i < bytes.len() {
+		if let Some(end) = skip_literal(input, i) {
+			i = end;
+			continue;
+		}
+		match &bytes[i..] {
+			[b'/', b'/', ..] => {
+				while i < bytes.len() && bytes[i] != b'\n' && bytes[i] != b'\r' {
+					output[i] = b' ';
+					i += 1;
+				}
+			}
+			[b'/', b'*', ..] => {
+				let mut depth = 0;
+				loop {
+					match &bytes[i..] {
+						[b'/', b'*', ..] => depth += 1,
+						[b'*', b'/', ..] => depth -= 1,
+						[] => return Err(lalrpop_util::ParseError::User { error: "A block comment is never closed" }),
+						[b'\n' | b'\r', ..] => {
+							i += 1;
+							continue;
+						}
+						_ => {
+							output[i] = b' ';
+							i += 1;
+							continue;
+						}
+					}
+					output[i] = b' ';
+					output[i + 1] = b' ';
+					i += 2;
+					if depth == 0 {
+						break;
+					}
+				}
+			}
+			_ => i += 1,
+		}
+	}
+
+	// Only whole characters were replaced, and by ASCII spaces.
+	Ok(std::string::String::from_utf8(output).unwrap())
+}
+
+/// Returns the offset of the last `{` which is never closed.
+/// Braces in comments, strings, characters and `#asm` blocks are not counted.
+pub fn unclosed_brace(input: &str) -> Option<usize> {
+	let blanked = blank_comments(input).ok()?;
+	let mut open = std::vec::Vec::new();
+	let mut i = 0;
+
+	while i < blanked.len() {
+		if let Some(end) = skip_literal(&blanked, i) {
+			i = end;
+			continue;
+		}
+		match blanked.as_bytes()[i] {
+			b'{' => open.push(i),
+			b'}' => {
+				open.pop();
+			}
+			_ => {}
+		}
+		i += 1;
+	}
+
+	open.pop()
+}
+
+/// The word of a syntax error where a name was expected, if it is a keyword.
+/// Keywords such as `data` or `switch` are reserved, so scripts which used them as names must rename them.
+pub fn reserved_word<'input>(err: &ParseError<'input>) -> Option<&'input str> {
+	match err {
+		ParseError::UnrecognizedToken { token: (_, lalrpop_util::lexer::Token(_, text), _), expected }
+			if text.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'_')
+				&& expected.iter().any(|i| i == r##"r#"[a-zA-Z_][a-zA-Z0-9_.]*"#"##) => Some(text),
+		_ => None,
+	}
+}
+
+/// Parses a whole file, returning every syntax error found if it is invalid.
+pub fn parse(input: &str) -> Result<std::vec::Vec<crate::types::Root>, std::vec::Vec<ParseError<'_>>> {
+	let blanked = blank_comments(input).map_err(|err| vec![err])?;
+	// Tokens in errors are slices of the blanked source, which has the same offsets as the original.
+	let original = |lalrpop_util::lexer::Token(kind, text): lalrpop_util::lexer::Token<'_>| {
+		let start = text.as_ptr() as usize - blanked.as_ptr() as usize;
+		lalrpop_util::lexer::Token(kind, &input[start..start + text.len()])
+	};
+
+	let mut recovered = std::vec::Vec::new();
+	let result = parser::FileParser::new().parse(&mut recovered, &blanked);
+	let mut errors = recovered.into_iter().map(|recovery| recovery.error.map_token(original)).collect::<std::vec::Vec<_>>();
+	let result = result.map_err(|err| err.map_token(original));
+
+	match result {
+		Ok(ast) if errors.is_empty() => Ok(ast),
+		Ok(_) => Err(errors),
+		Err(err) => {
+			errors.push(err);
+			Err(errors)
+		}
+	}
+}
+
+/// Parses an integer written as it would be in a script, such as `$FF`, `%1010` or `'A'`.
+pub fn parse_integer(text: &str) -> Option<i64> {
+	match parser::NumParser::new().parse(&mut std::vec::Vec::new(), text.trim()) {
+		Ok(crate::types::Rpn::Signed(value)) => Some(value),
+		_ => None,
+	}
+}
+
+/// Returns the source of a file with each of its includes replaced by the file's own preprocessed source.
+/// As when compiling, a file that was already included is left out.
+pub fn preprocess(path: &str, input: &str, include_paths: &[std::string::String]) -> Result<std::string::String, std::string::String> {
+	let mut included = std::collections::HashSet::new();
+	if let Ok(canonical_path) = std::fs::canonicalize(path) {
+		included.insert(canonical_path);
+	}
+	preprocess_file(path, input, include_paths, &mut included)
+}
+
+fn preprocess_file(
+	path: &str,
+	input: &str,
+	include_paths: &[std::string::String],
+	included: &mut std::collections::HashSet<std::path::PathBuf>,
+) -> Result<std::string::String, std::string::String> {
+	let ast = parse(input).map_err(|errors| {
+		errors.iter().map(|err| format!("{path}: {err}")).collect::<std::vec::Vec<_>>().join("\n")
+	})?;
+
+	let mut result = std::string::String::new();
+	let mut copied = 0;
+
+	for i in ast {
+		if let types::Root::Include { path: include_path, start, end } = i {
+			result += &input[copied..start];
+			copied = end;
+
+			let (include_path, include_input) = compiler::read_include(&include_path, path, include_paths)?;
+			if let Ok(canonical_path) = std::fs::canonicalize(&include_path) {
+				if !included.insert(canonical_path) {
+					continue;
+				}
+			}
+
+			result += &format!("// include \"{include_path}\"\n");
+			result += &preprocess_file(&include_path, &include_input, include_paths, included)?;
+			result += &format!("// end of \"{include_path}\"");
+		}
+	}
+
+	result += &input[copied..];
+	Ok(result)
 }
@@ -1,119 +1,510 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use codespan_reporting::diagnostic::{Diagnostic, Label};
 use codespan_reporting::files::SimpleFiles;
 use codespan_reporting::term;
 use codespan_reporting::term::termcolor::{ColorChoice, StandardStream};
-use evscript::compiler::CompilerOptions;
+use evscript::backend::RgbdsBackend;
+use evscript::compiler::{compile_with_header, CompilerError, CompilerOptions, MacroCheck};
 use lalrpop_util::ParseError;
 
+use std::collections::HashMap;
 use std::fs::read_to_string;
 use std::fs::File;
+use std::io;
+use std::io::IsTerminal;
+use std::io::Write;
 use std::process::exit;
 
+#[derive(Clone, Copy, ValueEnum)]
+enum Color {
+    /// Color diagnostics if standard error is a terminal, unless NO_COLOR is set
+    Auto,
+    Always,
+    Never,
+}
+
+impl Color {
+    fn choice(self) -> ColorChoice {
+        match self {
+            Color::Auto if io::stderr().is_terminal() => ColorChoice::Auto,
+            Color::Auto | Color::Never => ColorChoice::Never,
+            Color::Always => ColorChoice::Always,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum CheckMacros {
+    /// Assert that each macro's target is defined where it is used
+    Assert,
+    /// Warn with a list of the macro targets which were used
+    Warn,
+}
+
+impl CheckMacros {
+    fn check(self) -> MacroCheck {
+        match self {
+            CheckMacros::Assert => MacroCheck::Assert,
+            CheckMacros::Warn => MacroCheck::Warn,
+        }
+    }
+}
+
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
 struct Cli {
-    /// Output file
-    #[clap(short, long, value_parser, value_name = "PATH")]
+    /// Output file, or - for standard output, which is the default
+    #[clap(short, long, value_parser, value_name = "PATH", default_value = "-")]
     output: String,
 
+    /// Whether diagnostics are colored. They are always written to standard error, so the output is never colored
+    #[clap(long = "color", value_enum, default_value_t = Color::Auto)]
+    color: Color,
+
+    /// Preset the byte order, integer type, jumps and terminator of a kind of VM: gb-default,
+    /// or custom-be for a big-endian one with u16 integers, relative jumps and `ret` as terminator.
+    /// Other flags override the profile
+    #[clap(long = "target", value_name = "PROFILE")]
+    target: Option<String>,
+
+    /// Store 16-bit values and operands with their high byte first
+    #[clap(long = "big-endian", conflicts_with = "no_big_endian")]
+    big_endian: bool,
+
+    /// Store 16-bit values and operands with their low byte first, which is the default
+    #[clap(long = "no-big-endian")]
+    no_big_endian: bool,
+
+    /// Encode the target of each jump as its offset from the jump's opcode
+    #[clap(long = "relative-jumps", conflicts_with = "no_relative_jumps")]
+    relative_jumps: bool,
+
+    /// Encode the target of each jump as its address, which is the default
+    #[clap(long = "no-relative-jumps")]
+    no_relative_jumps: bool,
+
+    /// End each function with the definition NAME, instead of the one with bytecode 0
+    #[clap(long = "terminator", value_name = "NAME")]
+    terminator: Option<String>,
+
+    /// Type of integer constants in environments without a `default_int`, instead of u8
+    #[clap(long = "int-type", value_name = "TYPE")]
+    int_type: Option<String>,
+
     /// Report the peak memory usage of each function
     #[clap(long = "report-usage")]
     report_usage: bool,
 
-    /// Input file
+    /// Describe each environment's pool, including its reserved regions,
+    /// and how much of it each function uses, in comments in the output
+    #[clap(long = "pool-map")]
+    pool_map: bool,
+
+    /// List the definitions of each environment which no function uses,
+    /// directly or through an alias, so that the VM may leave them out
+    #[clap(long = "report-unused-funcs")]
+    report_unused_funcs: bool,
+
+    /// Do not end functions with a return. Execution falls through into the
+    /// next function, which is placed in the same section; the last one
+    /// runs into whatever follows it in ROM
+    #[clap(long = "no-terminator")]
+    no_terminator: bool,
+
+    /// Treat type mismatch warnings as errors
+    #[clap(long = "strict-types")]
+    strict_types: bool,
+
+    /// Warn when yielding with more than N variables live
+    #[clap(long = "check-yield-depth", value_name = "N")]
+    check_yield_depth: Option<usize>,
+
+    /// Fail if any function's bytecode is larger than N bytes
+    #[clap(long = "max-instructions", value_name = "N")]
+    max_instructions: Option<usize>,
+
+    /// Always call other functions with their bank. Otherwise, far calls are
+    /// only used when the caller and callee have different @bank attributes
+    #[clap(long = "far-calls")]
+    far_calls: bool,
+
+    /// Environment of the functions which do not specify one
+    #[clap(long = "assume-env", value_name = "NAME")]
+    assume_env: Option<String>,
+
+    /// Write a table of handlers indexed by bytecode for the given environment.
+    /// Handlers are named evs_<env>_<definition>; unused bytecodes point to evs_unimplemented
+    #[clap(long = "emit-dispatch", value_name = "ENV")]
+    emit_dispatch: Option<String>,
+
+    /// Fail if includes are nested more than N levels deep
+    #[clap(long = "include-depth-limit", value_name = "N", default_value_t = 64)]
+    include_depth_limit: usize,
+
+    /// Also search DIR for included files, after the including file's directory and the current one.
+    /// May be given several times
+    #[clap(short = 'I', value_name = "DIR")]
+    include_paths: Vec<String>,
+
+    /// Define the constant NAME, which is 1 unless VALUE is given, for use in constant expressions.
+    /// May be given several times
+    #[clap(short = 'D', value_name = "NAME[=VALUE]", value_parser = parse_define)]
+    defines: Vec<(String, i64)>,
+
+    /// Also write the environments' bytecode equates, type sizes and struct member offsets to PATH,
+    /// so that they can be included once by every file which needs them
+    #[clap(long = "emit-header", value_name = "PATH")]
+    emit_header: Option<String>,
+
+    /// Write a Makefile rule to PATH, making the output depend on the input and every file it includes
+    #[clap(long = "dep-file", value_name = "PATH")]
+    dep_file: Option<String>,
+
+    /// Leave the environments' bytecode equates out of the output, when they are included from a header instead
+    #[clap(long = "no-header")]
+    no_header: bool,
+
+    /// Leave out branches and loops which a constant condition makes unreachable,
+    /// such as the else of `if 1` or the body of `while 0`
+    #[clap(long = "optimize")]
+    optimize: bool,
+
+    /// Only check the input file's syntax, without compiling it or its includes
+    #[clap(long = "parse-only")]
+    parse_only: bool,
+
+    /// Fail unless the environment ENV is declared with `version N;`. May be given several times
+    #[clap(long = "require-env-version", value_name = "ENV=N", value_parser = parse_env_version)]
+    require_env_version: Vec<(String, i64)>,
+
+    /// Enable the given environment features, separated by commas
+    #[clap(long = "features", value_name = "FEATURES", value_delimiter = ',')]
+    features: Vec<String>,
+
+    /// Define <function>@<variable> to the slot of each variable, for use by assembly
+    #[clap(long = "variable-equates")]
+    variable_equates: bool,
+
+    /// Write the input's source with every include expanded, instead of compiling it
+    #[clap(long = "preprocess-only")]
+    preprocess_only: bool,
+
+    /// Compile without writing the output file, and print a summary of what would have been written
+    #[clap(long = "dry-run")]
+    dry_run: bool,
+
+    /// Precede each statement's bytecode with a comment naming its source file and line
+    #[clap(long = "line-directives")]
+    line_directives: bool,
+
+    /// Check that the assembler macros used by macro definitions exist
+    #[clap(long = "check-macros", value_enum, value_name = "HOW")]
+    check_macros: Option<CheckMacros>,
+
+    /// Do not print warnings, only errors
+    #[clap(long = "quiet")]
+    quiet: bool,
+
+    /// Input file, or - for standard input
     #[clap(value_parser, value_name = "PATH")]
     input: String,
 }
 
+/// The files diagnostics are rendered against: the one being compiled, and any included file one came from.
+struct Sources {
+    files: SimpleFiles<String, String>,
+    includes: HashMap<String, usize>,
+}
+
+impl Sources {
+    fn new(name: &str, source: &str) -> Sources {
+        let mut files = SimpleFiles::new();
+        files.add(String::from(name), String::from(source));
+        Sources { files, includes: HashMap::new() }
+    }
+
+    /// Finds the id of the file at `path`, reading it the first time it is needed.
+    fn include(&mut self, path: &str) -> Option<usize> {
+        if let Some(id) = self.includes.get(path) {
+            return Some(*id);
+        }
+        let id = self.files.add(String::from(path), read_to_string(path).ok()?);
+        self.includes.insert(String::from(path), id);
+        Some(id)
+    }
+
+    /// Labels `diagnostic` with the spans of `err`, in the file they belong to.
+    fn label(&mut self, diagnostic: Diagnostic<usize>, err: CompilerError) -> Diagnostic<usize> {
+        let file_id = match &err.file {
+            Some(path) => self.include(path),
+            None => Some(0),
+        };
+        match (err.get_range(), file_id) {
+            (Some(range), Some(file_id)) => {
+                let mut labels = vec![Label::primary(file_id, range)];
+                if let Some(previous) = err.previous {
+                    labels.push(Label::secondary(file_id, previous).with_message("previously defined here"));
+                }
+                diagnostic.with_labels(labels).with_message(err.msg)
+            }
+            // Without a span to point at, the included file is at least named.
+            _ => match err.file {
+                Some(path) => diagnostic.with_message(format!("{path}: {}", err.msg)),
+                None => diagnostic.with_message(err.msg),
+            },
+        }
+    }
+}
+
+fn parse_env_version(arg: &str) -> Result<(String, i64), String> {
+    let (env, version) = arg.split_once('=').ok_or("expected ENV=N")?;
+    let version = version.parse().map_err(|err| format!("invalid version {version:?}: {err}"))?;
+    Ok((String::from(env), version))
+}
+
+fn parse_define(arg: &str) -> Result<(String, i64), String> {
+    let (name, value) = match arg.split_once('=') {
+        Some((name, value)) => {
+            let value = evscript::parse_integer(value).ok_or(format!("invalid integer {value:?}"))?;
+            (name, value)
+        }
+        None => (arg, 1),
+    };
+    let mut chars = name.chars();
+    if !chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_') || !chars.all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(format!("invalid name {name:?}"));
+    }
+    Ok((String::from(name), value))
+}
+
 fn main() {
     let cli = Cli::parse();
 
-    let input = &match read_to_string(&cli.input) {
+    // A script piped in through standard input includes files relative to the current directory.
+    let (input_name, input) = if cli.input == "-" {
+        ("<stdin>", io::read_to_string(io::stdin()))
+    } else {
+        (cli.input.as_str(), read_to_string(&cli.input))
+    };
+    let input = &match input {
         Ok(input) => input,
         Err(err) => {
-            eprintln!("{}: {err}", cli.input);
+            eprintln!("{input_name}: {err}");
             exit(1);
         }
     };
 
-    let mut output = match File::create(&cli.output) {
-        Ok(f) => f,
-        Err(err) => {
-            eprintln!("{}: {err}", cli.output);
-            exit(1);
+    let mut output: Box<dyn Write> = if cli.dry_run || cli.parse_only {
+        Box::new(io::sink())
+    } else if cli.output == "-" {
+        Box::new(io::stdout())
+    } else {
+        match File::create(&cli.output) {
+            Ok(f) => Box::new(f),
+            Err(err) => {
+                eprintln!("{}: {err}", cli.output);
+                exit(1);
+            }
         }
     };
 
     let ast = match evscript::parse(input) {
         Ok(ast) => ast,
-        Err(err) => {
-            let (message, range) = match err {
-                ParseError::InvalidToken { location } => {
-                    (String::from("Invalid token"), Some(location..location))
-                }
-                ParseError::UnrecognizedEof { location, expected } => {
-                    let mut message = format!("Unexpected EOF, expected one of:");
-                    for i in expected {
-                        message += " ";
-                        message += &i;
+        Err(errors) => {
+            let mut files = SimpleFiles::new();
+            let file_id = files.add(input_name, input);
+            let writer = StandardStream::stderr(cli.color.choice());
+            let config = term::Config::default();
+
+            for err in errors {
+                let (message, range) = match err {
+                    ParseError::InvalidToken { location } => {
+                        (String::from("Invalid token"), Some(location..location))
                     }
-                    (message, Some(location..location))
-                }
-                ParseError::UnrecognizedToken { token, expected } => {
-                    let (l, t, r) = token;
-                    let mut message = format!("Unexepected token. Got \"{t}\", expected one of:");
-                    for i in expected {
-                        message += " ";
-                        message += &i;
+                    ParseError::UnrecognizedEof { location: _, expected } if expected.iter().any(|i| i == "\"}\"") && evscript::unclosed_brace(input).is_some() => {
+                        let brace = evscript::unclosed_brace(input).unwrap();
+                        (String::from("This block is never closed"), Some(brace..brace + 1))
                     }
-                    (message, Some(l..r))
-                }
-                ParseError::ExtraToken { token } => {
-                    let (l, t, r) = token;
-                    (format!("Extra token: \"{t}\""), Some(l..r))
-                }
-                ParseError::User { error } => (error.to_string(), None::<std::ops::Range<usize>>),
-            };
-            let mut files = SimpleFiles::new();
-            let file_id = files.add(&cli.input, input);
+                    ParseError::UnrecognizedEof { location, expected } => {
+                        let mut message = format!("Unexpected EOF, expected one of:");
+                        for i in expected {
+                            message += " ";
+                            message += &i;
+                        }
+                        (message, Some(location..location))
+                    }
+                    ref err @ ParseError::UnrecognizedToken { token: (l, _, r), .. } if evscript::reserved_word(err).is_some() => {
+                        let word = evscript::reserved_word(err).unwrap();
+                        (format!("{word} is a reserved word, and cannot be used as a name"), Some(l..r))
+                    }
+                    ParseError::UnrecognizedToken { token, expected } => {
+                        let (l, t, r) = token;
+                        let mut message = format!("Unexepected token. Got \"{t}\", expected one of:");
+                        for i in expected {
+                            message += " ";
+                            message += &i;
+                        }
+                        (message, Some(l..r))
+                    }
+                    ParseError::ExtraToken { token } => {
+                        let (l, t, r) = token;
+                        (format!("Extra token: \"{t}\""), Some(l..r))
+                    }
+                    ParseError::User { error } => (error.to_string(), None::<std::ops::Range<usize>>),
+                };
 
-            let diagnostic = if let Some(range) = range {
-                Diagnostic::error()
-                    .with_labels(vec![Label::primary(file_id, range)])
-                    .with_message(message)
-            } else {
-                Diagnostic::error().with_message(message)
-            };
+                let diagnostic = if let Some(range) = range {
+                    Diagnostic::error()
+                        .with_labels(vec![Label::primary(file_id, range)])
+                        .with_message(message)
+                } else {
+                    Diagnostic::error().with_message(message)
+                };
 
-            let writer = StandardStream::stderr(ColorChoice::Auto);
-            let config = term::Config::default();
-            match term::emit(&mut writer.lock(), &config, &files, &diagnostic) {
-                Err(err) => eprintln!("Failed to print error: {err}"),
-                _ => {}
+                match term::emit(&mut writer.lock(), &config, &files, &diagnostic) {
+                    Err(err) => eprintln!("Failed to print error: {err}"),
+                    _ => {}
+                }
             }
             exit(1);
         }
     };
 
-    let mut compiler_options = CompilerOptions::new();
-    compiler_options.report_usage = cli.report_usage;
+    if cli.parse_only {
+        return;
+    }
 
-    if let Err(err) = evscript::compile(ast, &cli.input, &mut output, compiler_options) {
-        let mut files = SimpleFiles::new();
-        let file_id = files.add(&cli.input, input);
-
-        let diagnostic = if let Some(range) = err.get_range() {
-            Diagnostic::error()
-                .with_labels(vec![Label::primary(file_id, range)])
-                .with_message(err.msg)
-        } else {
-            Diagnostic::error().with_message(err.msg)
+    if cli.preprocess_only {
+        let result = evscript::preprocess(input_name, input, &cli.include_paths)
+            .and_then(|source| output.write_all(source.as_bytes()).map_err(|err| format!("{}: {err}", cli.output)));
+        if let Err(err) = result {
+            eprintln!("{err}");
+            exit(1);
+        }
+        return;
+    }
+
+    let mut compiler_options = match &cli.target {
+        Some(target) => match CompilerOptions::target(target) {
+            Ok(options) => options,
+            Err(err) => {
+                eprintln!("{err}");
+                exit(1);
+            }
+        },
+        None => CompilerOptions::new(),
+    };
+    compiler_options.report_unused |= cli.report_unused_funcs;
+    compiler_options.pool_map |= cli.pool_map;
+    compiler_options.optimize |= cli.optimize;
+    compiler_options.no_terminator |= cli.no_terminator;
+    compiler_options.strict_types |= cli.strict_types;
+    compiler_options.yield_depth_limit = cli.check_yield_depth;
+    compiler_options.max_function_size = cli.max_instructions;
+    compiler_options.far_calls |= cli.far_calls;
+    compiler_options.assumed_environment = cli.assume_env;
+    compiler_options.dispatch_environment = cli.emit_dispatch;
+    compiler_options.required_versions = cli.require_env_version;
+    compiler_options.features = cli.features;
+    compiler_options.variable_equates |= cli.variable_equates;
+    compiler_options.include_depth_limit = cli.include_depth_limit;
+    compiler_options.include_paths = cli.include_paths;
+    compiler_options.defines = cli.defines.into_iter().collect();
+    // A flag overrides the profile only when it is given, either way for those with a --no- form.
+    if cli.big_endian || cli.no_big_endian {
+        compiler_options.big_endian = cli.big_endian;
+    }
+    if cli.relative_jumps || cli.no_relative_jumps {
+        compiler_options.relative_jumps = cli.relative_jumps;
+    }
+    compiler_options.terminator = cli.terminator.or(compiler_options.terminator);
+    compiler_options.default_int = cli.int_type.or(compiler_options.default_int);
+    compiler_options.line_directives |= cli.line_directives;
+    compiler_options.check_macros = cli.check_macros.map(CheckMacros::check);
+    compiler_options.no_header |= cli.no_header;
+
+    let mut warnings = Vec::new();
+    // The header is written once compilation succeeds, so that a failure doesn't leave a partial one.
+    let mut header = cli.emit_header.as_ref().filter(|_| !cli.dry_run).map(|_| RgbdsBackend::new(Vec::new()));
+    let result = match &mut header {
+        Some(header) => compile_with_header(ast, input_name, input, &mut RgbdsBackend::new(&mut output), header, compiler_options, &mut warnings),
+        None => evscript::compile(ast, input_name, input, &mut output, compiler_options, &mut warnings),
+    };
+
+    if !warnings.is_empty() && !cli.quiet {
+        let mut sources = Sources::new(input_name, input);
+        let writer = StandardStream::stderr(cli.color.choice());
+        let config = term::Config::default();
+
+        for warning in warnings {
+            let diagnostic = sources.label(Diagnostic::warning(), warning);
+
+            if let Err(err) = term::emit(&mut writer.lock(), &config, &sources.files, &diagnostic) {
+                eprintln!("Failed to print warning: {err}");
+            }
+        }
+    }
+
+    if let (Ok(summary), true) = (&result, cli.dry_run) {
+        // Macros are expanded by the assembler, so the size of functions using them isn't known.
+        let (at_least, macros) = match summary.macro_invocations {
+            0 => ("", String::new()),
+            count => ("at least ", format!(", {count} macro invocations of unknown size")),
         };
+        eprintln!(
+            "{}: {} functions ({at_least}{} bytes{macros}), {} data sections ({} bytes), {at_least}{} bytes total",
+            cli.output,
+            summary.functions,
+            summary.function_bytes,
+            summary.data_sections,
+            summary.data_bytes,
+            summary.function_bytes + summary.data_bytes,
+        );
+    }
+
+    // Like the usage report, this is printed to stderr so that it can't end up in the assembly.
+    if let Ok(summary) = &result {
+        for (env, name) in &summary.unused_definitions {
+            eprintln!("({env}) Unused: {name}");
+        }
+    }
+
+    if let (Ok(summary), true) = (&result, cli.report_usage) {
+        // Printed to stderr so that it can't end up in assembly written to stdout.
+        for (name, peak) in &summary.peak_usage {
+            eprintln!("{name}: {peak} bytes peak");
+        }
+    }
+
+    if let (Ok(summary), Some(path), false) = (&result, &cli.dep_file, cli.dry_run) {
+        let escape = |path: &str| path.replace('$', "$$").replace(' ', "\\ ");
+        let mut rule = escape(&cli.output) + ":";
+        // Standard input is not a file that make could check.
+        let root = (cli.input != "-").then_some(&cli.input);
+        for i in root.into_iter().chain(&summary.included_files) {
+            rule += " ";
+            rule += &escape(i);
+        }
+        if let Err(err) = std::fs::write(path, rule + "\n") {
+            eprintln!("{path}: {err}");
+            exit(1);
+        }
+    }
+
+    if let (Ok(..), Some(path), Some(header)) = (&result, &cli.emit_header, header) {
+        if let Err(err) = std::fs::write(path, header.output) {
+            eprintln!("{path}: {err}");
+            exit(1);
+        }
+    }
+
+    if let Err(err) = result {
+        let mut sources = Sources::new(input_name, input);
+        let diagnostic = sources.label(Diagnostic::error(), err);
 
-        let writer = StandardStream::stderr(ColorChoice::Auto);
+        let writer = StandardStream::stderr(cli.color.choice());
         let config = term::Config::default();
-        match term::emit(&mut writer.lock(), &config, &files, &diagnostic) {
+        match term::emit(&mut writer.lock(), &config, &sources.files, &diagnostic) {
             Err(err) => eprintln!("Failed to print error: {err}"),
             _ => {}
         }
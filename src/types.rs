@@ -1,30 +1,60 @@
+use std::collections::HashMap;
 use std::vec::Vec;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Statement {
 	pub t: StatementType,
 	pub start: usize,
 	pub end: usize,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum StatementType {
 	// Environment statements
 	Use(String),
 	Definition(String, Definition),
 	Pool(Rpn),
+	Version(Rpn),
+	// The value of `null` pointers.
+	Null(Rpn),
+	// The type given to integer constants.
+	DefaultInt(String),
+	// A number of bytes, or a variable, placed at a fixed offset in the pool.
+	Reserve(Rpn, Rpn),
+	ReserveVariable(String, String, Rpn),
+	// A constant condition checked at compile time, and an optional message to fail with.
+	StaticAssert(Rpn, Option<String>),
+	// Statements which are only included if the named feature is enabled.
+	Feature(String, Vec<Statement>),
 	// Function statements
 	Expression(Rpn),
 	Declaration(String, String),
-	PointerDeclaration(String, String),
+	// The pointee type, how many levels of pointers there are, and the name.
+	PointerDeclaration(String, usize, String),
 	DeclareAssign(String, String, Rpn),
-	PointerDeclareAssign(String, String, Rpn),
+	// A declaration with a braced list of values for each member.
+	DeclareInitialize(String, String, Vec<Rpn>),
+	PointerDeclareAssign(String, usize, String, Rpn),
 	If(Rpn, Vec<Statement>, Option<Vec<Statement>>),
 	While(Rpn, Vec<Statement>),
 	Do(Rpn, Vec<Statement>),
 	For(Box<Statement>, Rpn, Box<Statement>, Vec<Statement>),
-	Repeat(Rpn, Vec<Statement>),
+	// The count, the contents, and attributes such as `@unroll`.
+	Repeat(Rpn, Vec<Statement>, Vec<Attribute>),
 	Loop(Vec<Statement>),
+	// The value, its cases, and the optional `else` case.
+	Switch(Rpn, Vec<Case>, Option<Vec<Statement>>),
+	// Raw bytes (or words) emitted in place, with an optional label.
+	Data(Option<String>, u8, Vec<Rpn>),
+}
+
+/// A `case` of a `switch` statement.
+#[derive(Debug, Clone)]
+pub struct Case {
+	pub values: Vec<Rpn>,
+	pub contents: Vec<Statement>,
+	/// Whether the case ends in `fallthrough;` rather than leaving the `switch`.
+	pub fallthrough: bool,
 }
 
 #[derive(Debug)]
@@ -32,29 +62,46 @@ pub enum Root {
 	Environment(String, Environment),
 	Function(String, Function),
 	Assembly(String),
-	Include(String),
-	Typedef { name: String, t: String },
-	Struct { name: String, contents: Vec<StructMember> },
+	Include { path: String, start: usize, end: usize },
+	Typedef { name: String, t: String, start: usize, end: usize },
+	Struct { name: String, contents: Vec<StructMember>, start: usize, end: usize },
+	Data { label: Option<String>, size: u8, values: Vec<Rpn>, start: usize, end: usize },
+	StaticAssert { condition: Rpn, message: Option<String>, start: usize, end: usize },
 }
 
 // Top-level statements.
 #[derive(Debug)]
 pub struct Environment {
-	pub contents: Vec<Statement>
+	pub contents: Vec<Statement>,
+	pub start: usize,
+	pub end: usize,
 }
 
 #[derive(Debug)]
 pub struct Function {
-	pub environment: String,
+	pub attributes: Vec<Attribute>,
+	/// `None` if the function omits its environment, in which case the assumed one is used.
+	pub environment: Option<String>,
 	pub contents: Vec<Statement>,
 	pub start: usize,
 	pub end: usize,
 }
 
+/// An annotation such as `@falls_through`, `@bank(2)` or `@unroll(4)`.
+#[derive(Debug, Clone)]
+pub struct Attribute {
+	pub name: String,
+	pub args: Vec<Rpn>,
+	pub start: usize,
+	pub end: usize,
+}
+
 #[derive(Debug)]
 pub struct StructMember {
 	pub name: String,
-	pub t: String
+	pub t: String,
+	pub start: usize,
+	pub end: usize,
 }
 
 // Environment statements
@@ -90,6 +137,7 @@ pub enum DefinitionParam {
 	Return(String),
 	Const(String),
 	Type(String),
+	Pointer(String),
 }
 
 #[derive(Debug, Clone)]
@@ -99,18 +147,64 @@ pub enum AliasParam {
 	Const(Rpn),
 }
 
+#[derive(Debug, Clone, Copy)]
+pub enum Comparison {
+	Equ,
+	NotEqu,
+	LessThan,
+	GreaterThan,
+	LessThanEqu,
+	GreaterThanEqu,
+}
+
+impl Comparison {
+	/// The name of the bytecode implementing this comparison, without its type.
+	pub fn opcode(&self) -> &'static str {
+		match self {
+			Comparison::Equ => "equ",
+			Comparison::NotEqu => "nequ",
+			Comparison::LessThan => "lt",
+			Comparison::GreaterThan => "gt",
+			Comparison::LessThanEqu => "lte",
+			Comparison::GreaterThanEqu => "gte",
+		}
+	}
+
+	pub fn eval(&self, l: i64, r: i64) -> bool {
+		match self {
+			Comparison::Equ => l == r,
+			Comparison::NotEqu => l != r,
+			Comparison::LessThan => l < r,
+			Comparison::GreaterThan => l > r,
+			Comparison::LessThanEqu => l <= r,
+			Comparison::GreaterThanEqu => l >= r,
+		}
+	}
+}
+
 #[derive(Debug, Clone)]
 pub enum Rpn {
 	// Values
 	Variable(String),
 	Signed(i64),
+	// An 8.8 fixed-point literal, stored as its raw 16-bit representation.
+	Fixed(i64),
 	String(String),
-	Call(String, Vec<Rpn>),
+	// The length of a string literal, as a constant.
+	StrLen(String),
+	// The size of a type in bytes, as a constant.
+	SizeOf(String),
+	// A pointer to nothing, whose value is set by the environment.
+	Null,
+	// A call, with where it is in the source, so errors about its result can point at it.
+	Call(String, Vec<Rpn>, usize, usize),
 	// Unary
 	Negate(Box<Rpn>),
 	Deref(Box<Rpn>),
 	Not(Box<Rpn>),
 	Address(String),
+	// Reinterprets a value as a pointer to the given type.
+	PointerCast(String, Box<Rpn>),
 	// Factors
 	Mul(Box<Rpn>, Box<Rpn>),
 	Div(Box<Rpn>, Box<Rpn>),
@@ -132,46 +226,232 @@ pub enum Rpn {
 	GreaterThan(Box<Rpn>, Box<Rpn>),
 	LessThanEqu(Box<Rpn>, Box<Rpn>),
 	GreaterThanEqu(Box<Rpn>, Box<Rpn>),
+	// `a < b < c`, which is true if each comparison is. Middle operands are evaluated once.
+	ComparisonChain(Vec<Rpn>, Vec<Comparison>),
 	// Logicals
 	LogicalAnd(Box<Rpn>, Box<Rpn>),
 	LogicalOr(Box<Rpn>, Box<Rpn>),
+	// `condition ? a : b`
+	Select(Box<Rpn>, Box<Rpn>, Box<Rpn>),
 	// += is constructed using a Set(self, Add(self, <expression>))
-	Set(String, Box<Rpn>),
+	// The variable's location in the source comes last, for errors about it.
+	Set(String, Box<Rpn>, usize, usize),
+}
+
+/// A piece of a string literal, once its escape sequences are decoded.
+#[derive(Debug, PartialEq)]
+pub enum StringPiece {
+	/// Characters, which the assembler passes through its charmap.
+	Text(String),
+	/// A byte written as `\0` or `\xNN`, which is emitted as it is.
+	Byte(u8),
+}
+
+/// Decodes the escape sequences of a string literal: `\n`, `\t`, `\\` and `\"` stand for those characters,
+/// and `\0` and `\xNN` for a byte.
+pub fn unescape(text: &str) -> Result<Vec<StringPiece>, String> {
+	let mut pieces = Vec::new();
+	let mut current = String::new();
+	let mut chars = text.chars();
+
+	while let Some(c) = chars.next() {
+		if c != '\\' {
+			current.push(c);
+			continue;
+		}
+		let byte = match chars.next() {
+			Some('n') => { current.push('\n'); continue; }
+			Some('t') => { current.push('\t'); continue; }
+			Some('\\') => { current.push('\\'); continue; }
+			Some('"') => { current.push('"'); continue; }
+			Some('0') => 0,
+			Some('x') => {
+				let digits = chars.by_ref().take(2).collect::<String>();
+				match u8::from_str_radix(&digits, 16) {
+					Ok(byte) if digits.len() == 2 => byte,
+					_ => return Err(String::from("\\x must be followed by two hexadecimal digits")),
+				}
+			}
+			Some(c) => return Err(format!("Unknown escape sequence \\{c} (expected \\n, \\t, \\0, \\\\, \\\" or \\xNN)")),
+			None => return Err(String::from("Unterminated escape sequence at the end of the string")),
+		};
+		if !current.is_empty() {
+			pieces.push(StringPiece::Text(std::mem::take(&mut current)));
+		}
+		pieces.push(StringPiece::Byte(byte));
+	}
+	if !current.is_empty() {
+		pieces.push(StringPiece::Text(current));
+	}
+
+	Ok(pieces)
+}
+
+/// The number of bytes in a string, with each escape sequence counting as the one byte it stands for.
+pub fn string_length(text: &str) -> Result<usize, String> {
+	Ok(unescape(text)?.iter()
+		.map(|piece| match piece {
+			StringPiece::Text(text) => text.len(),
+			StringPiece::Byte(..) => 1,
+		})
+		.sum())
+}
+
+/// Divides two constants, failing rather than panicking on a division by zero.
+pub fn const_div(l: i64, r: i64) -> Result<i64, String> {
+	l.checked_div(r).ok_or(String::from("Division by zero"))
+}
+
+pub fn const_mod(l: i64, r: i64) -> Result<i64, String> {
+	l.checked_rem(r).ok_or(String::from("Division by zero"))
+}
+
+/// Shifts a constant, failing if the shift amount is negative or no narrower than an i64.
+pub fn const_shl(l: i64, r: i64) -> Result<i64, String> {
+	u32::try_from(r).ok().and_then(|r| l.checked_shl(r)).ok_or(String::from("Shift amount out of range"))
+}
+
+pub fn const_shr(l: i64, r: i64) -> Result<i64, String> {
+	u32::try_from(r).ok().and_then(|r| l.checked_shr(r)).ok_or(String::from("Shift amount out of range"))
 }
 
 impl Rpn {
 	pub fn eval_const(&self) -> Result<i64, String> {
+		self.eval_const_with(&|t| Err(format!("The size of {t} is not known here")))
+	}
+
+	/// Evaluates a constant expression which may use the names of `symbols`.
+	pub fn eval_const_defined(&self, symbols: &HashMap<String, i64>) -> Result<i64, String> {
+		self.eval_const_in(&|t| Err(format!("The size of {t} is not known here")), symbols)
+	}
+
+	/// Evaluates a constant expression, using `size_of` to find the size of types for `sizeof`.
+	pub fn eval_const_with(&self, size_of: &dyn Fn(&str) -> Result<i64, String>) -> Result<i64, String> {
+		self.eval_const_in(size_of, &HashMap::new())
+	}
+
+	/// Evaluates a constant expression in which the names of `symbols`, such as those defined on the command line, may be used.
+	pub fn eval_const_in(&self, size_of: &dyn Fn(&str) -> Result<i64, String>, symbols: &HashMap<String, i64>) -> Result<i64, String> {
 		Ok(match self {
-			Rpn::Variable(..) => return Err(format!("Unexpected variable, expression must be constant")),
-			Rpn::String(..) => return Err(format!("Unexpected string, expression must be constant")),
-			Rpn::Call(..) => return Err(format!("Unexpected call, expression must be constant")),
-			Rpn::Deref(..) => return Err(format!("Unexpected dereference, expression must be constant")),
-			Rpn::Address(..) => return Err(format!("Unexpected address operator, expression must be constant")),
-			Rpn::Set(..) => return Err(format!("Unexpected assignment, expression must be constant")),
+			Rpn::Variable(name) => match symbols.get(name) {
+				Some(value) => *value,
+				None => return Err(String::from("Unexpected variable, expression must be constant")),
+			},
+			Rpn::String(..) => return Err(String::from("Unexpected string, expression must be constant")),
+			Rpn::Call(..) => return Err(String::from("Unexpected call, expression must be constant")),
+			Rpn::Deref(..) => return Err(String::from("Unexpected dereference, expression must be constant")),
+			Rpn::Address(..) => return Err(String::from("Unexpected address operator, expression must be constant")),
+			Rpn::Set(..) => return Err(String::from("Unexpected assignment, expression must be constant")),
+			Rpn::Null => return Err(String::from("Unexpected null, expression must be constant")),
 
 			Rpn::Signed(value) => *value,
+			Rpn::SizeOf(t) => size_of(t)?,
+			Rpn::Fixed(value) => *value,
+			Rpn::StrLen(text) => string_length(text)? as i64,
+
+			Rpn::Negate(i) => -i.eval_const_in(size_of, symbols)?,
+			// Like at runtime, where it is an exclusive or with all bits set, `!` is bitwise.
+			Rpn::Not(i) => !i.eval_const_in(size_of, symbols)?,
+			Rpn::PointerCast(_, i) => i.eval_const_in(size_of, symbols)?,
 
-			Rpn::Negate(i) => i.eval_const()?,
-			Rpn::Not(i) => i.eval_const()?,
-
-			Rpn::Mul(l, r) => l.eval_const()? * r.eval_const()?,
-			Rpn::Div(l, r) => l.eval_const()? / r.eval_const()?,
-			Rpn::Mod(l, r) => l.eval_const()? % r.eval_const()?,
-			Rpn::Add(l, r) => l.eval_const()? + r.eval_const()?,
-			Rpn::Sub(l, r) => l.eval_const()? - r.eval_const()?,
-			Rpn::ShiftLeft(l, r) => l.eval_const()? << r.eval_const()?,
-			Rpn::ShiftRight(l, r) => l.eval_const()? >> r.eval_const()?,
-			Rpn::BinaryAnd(l, r) => l.eval_const()? & r.eval_const()?,
-			Rpn::BinaryXor(l, r) => l.eval_const()? ^ r.eval_const()?,
-			Rpn::BinaryOr(l, r) => l.eval_const()? | r.eval_const()?,
-			Rpn::Equ(l, r) => (l.eval_const()? == r.eval_const()?) as i64,
-			Rpn::NotEqu(l, r) => (l.eval_const()? != r.eval_const()?) as i64,
-			Rpn::LessThan(l, r) => (l.eval_const()? < r.eval_const()?) as i64,
-			Rpn::GreaterThan(l, r) => (l.eval_const()? > r.eval_const()?) as i64,
-			Rpn::LessThanEqu(l, r) => (l.eval_const()? <= r.eval_const()?) as i64,
-			Rpn::GreaterThanEqu(l, r) => (l.eval_const()? >= r.eval_const()?) as i64,
-			Rpn::LogicalAnd(l, r) => (l.eval_const()? != 0 && r.eval_const()? != 0) as i64,
-			Rpn::LogicalOr(l, r) => (l.eval_const()? != 0 || r.eval_const()? != 0) as i64,
+			Rpn::Mul(l, r) => l.eval_const_in(size_of, symbols)? * r.eval_const_in(size_of, symbols)?,
+			Rpn::Div(l, r) => const_div(l.eval_const_in(size_of, symbols)?, r.eval_const_in(size_of, symbols)?)?,
+			Rpn::Mod(l, r) => const_mod(l.eval_const_in(size_of, symbols)?, r.eval_const_in(size_of, symbols)?)?,
+			Rpn::Add(l, r) => l.eval_const_in(size_of, symbols)? + r.eval_const_in(size_of, symbols)?,
+			Rpn::Sub(l, r) => l.eval_const_in(size_of, symbols)? - r.eval_const_in(size_of, symbols)?,
+			Rpn::ShiftLeft(l, r) => const_shl(l.eval_const_in(size_of, symbols)?, r.eval_const_in(size_of, symbols)?)?,
+			Rpn::ShiftRight(l, r) => const_shr(l.eval_const_in(size_of, symbols)?, r.eval_const_in(size_of, symbols)?)?,
+			Rpn::BinaryAnd(l, r) => l.eval_const_in(size_of, symbols)? & r.eval_const_in(size_of, symbols)?,
+			Rpn::BinaryXor(l, r) => l.eval_const_in(size_of, symbols)? ^ r.eval_const_in(size_of, symbols)?,
+			Rpn::BinaryOr(l, r) => l.eval_const_in(size_of, symbols)? | r.eval_const_in(size_of, symbols)?,
+			Rpn::Equ(l, r) => (l.eval_const_in(size_of, symbols)? == r.eval_const_in(size_of, symbols)?) as i64,
+			Rpn::NotEqu(l, r) => (l.eval_const_in(size_of, symbols)? != r.eval_const_in(size_of, symbols)?) as i64,
+			Rpn::LessThan(l, r) => (l.eval_const_in(size_of, symbols)? < r.eval_const_in(size_of, symbols)?) as i64,
+			Rpn::GreaterThan(l, r) => (l.eval_const_in(size_of, symbols)? > r.eval_const_in(size_of, symbols)?) as i64,
+			Rpn::LessThanEqu(l, r) => (l.eval_const_in(size_of, symbols)? <= r.eval_const_in(size_of, symbols)?) as i64,
+			Rpn::GreaterThanEqu(l, r) => (l.eval_const_in(size_of, symbols)? >= r.eval_const_in(size_of, symbols)?) as i64,
+			Rpn::ComparisonChain(operands, comparisons) => {
+				let mut result = true;
+				for (i, comparison) in comparisons.iter().enumerate() {
+					result &= comparison.eval(operands[i].eval_const_in(size_of, symbols)?, operands[i + 1].eval_const_in(size_of, symbols)?);
+				}
+				result as i64
+			}
+			Rpn::LogicalAnd(l, r) => (l.eval_const_in(size_of, symbols)? != 0 && r.eval_const_in(size_of, symbols)? != 0) as i64,
+			Rpn::LogicalOr(l, r) => (l.eval_const_in(size_of, symbols)? != 0 || r.eval_const_in(size_of, symbols)? != 0) as i64,
+			Rpn::Select(condition, then_value, else_value) => match condition.eval_const_in(size_of, symbols)? {
+				0 => else_value.eval_const_in(size_of, symbols)?,
+				_ => then_value.eval_const_in(size_of, symbols)?,
+			},
 		})
 	}
+
+	/// Builds a comparison, or a chain of them, folding it if every operand is constant.
+	pub fn comparison(first: Rpn, rest: Vec<(Comparison, Rpn)>) -> Rpn {
+		let mut operands = vec![first];
+		let mut comparisons = Vec::new();
+		for (comparison, operand) in rest {
+			comparisons.push(comparison);
+			operands.push(operand);
+		}
+
+		if operands.iter().all(|i| matches!(i, Rpn::Signed(..))) {
+			if let Ok(value) = Rpn::ComparisonChain(operands.clone(), comparisons.clone()).eval_const() {
+				return Rpn::Signed(value);
+			}
+		}
+
+		if comparisons.len() > 1 {
+			return Rpn::ComparisonChain(operands, comparisons);
+		}
+
+		let r = Box::new(operands.pop().unwrap());
+		let l = Box::new(operands.pop().unwrap());
+		match comparisons[0] {
+			Comparison::Equ => Rpn::Equ(l, r),
+			Comparison::NotEqu => Rpn::NotEqu(l, r),
+			Comparison::LessThan => Rpn::LessThan(l, r),
+			Comparison::GreaterThan => Rpn::GreaterThan(l, r),
+			Comparison::LessThanEqu => Rpn::LessThanEqu(l, r),
+			Comparison::GreaterThanEqu => Rpn::GreaterThanEqu(l, r),
+		}
+	}
+
+	/// If this expression is the address of a label plus or minus a constant,
+	/// returns both so that the linker may compute it.
+	pub fn label_offset(&self) -> Option<(String, i64)> {
+		match self {
+			Rpn::Address(label) => Some((label.clone(), 0)),
+			Rpn::Add(l, r) => {
+				if let Some((label, offset)) = l.label_offset() {
+					Some((label, offset + r.eval_const().ok()?))
+				} else {
+					let (label, offset) = r.label_offset()?;
+					Some((label, offset + l.eval_const().ok()?))
+				}
+			}
+			Rpn::Sub(l, r) => {
+				let (label, offset) = l.label_offset()?;
+				Some((label, offset - r.eval_const().ok()?))
+			}
+			_ => None,
+		}
+	}
+
+	/// The expressions this one is computed from, in the order they are evaluated.
+	pub fn operands(&self) -> Vec<&Rpn> {
+		match self {
+			Rpn::Variable(..) | Rpn::Signed(..) | Rpn::Fixed(..) | Rpn::String(..) | Rpn::StrLen(..)
+			| Rpn::SizeOf(..) | Rpn::Null | Rpn::Address(..) => vec![],
+			Rpn::Call(_, args, ..) | Rpn::ComparisonChain(args, _) => args.iter().collect(),
+			Rpn::Negate(i) | Rpn::Deref(i) | Rpn::Not(i) | Rpn::PointerCast(_, i) | Rpn::Set(_, i, ..) => vec![i],
+			Rpn::Mul(l, r) | Rpn::Div(l, r) | Rpn::Mod(l, r) | Rpn::Add(l, r) | Rpn::Sub(l, r)
+			| Rpn::ShiftLeft(l, r) | Rpn::ShiftRight(l, r)
+			| Rpn::BinaryAnd(l, r) | Rpn::BinaryXor(l, r) | Rpn::BinaryOr(l, r)
+			| Rpn::Equ(l, r) | Rpn::NotEqu(l, r) | Rpn::LessThan(l, r) | Rpn::GreaterThan(l, r)
+			| Rpn::LessThanEqu(l, r) | Rpn::GreaterThanEqu(l, r)
+			| Rpn::LogicalAnd(l, r) | Rpn::LogicalOr(l, r) => vec![l, r],
+			Rpn::Select(condition, then_value, else_value) => vec![condition, then_value, else_value],
+		}
+	}
 }
@@ -0,0 +1,52 @@
+mod common;
+
+use common::*;
+use evscript::compiler::CompilerOptions;
+
+#[test]
+fn offsets_from_local_addresses_are_constant() {
+	let run = run_f("script f {\n\tu8 a = 1;\n\tu16 b = 2;\n\tu16 p = &b;\n\tu16 q = &b + 1;\n\tu16 r = 3 + &b - 2;\n}\n");
+	let b = run.word("p");
+	assert_ne!(b, 0);
+	assert_eq!(run.word("q"), b + 1);
+	assert_eq!(run.word("r"), b + 1);
+}
+
+#[test]
+fn variable_equates_name_each_slot_for_assembly() {
+	let options = CompilerOptions { variable_equates: true, ..CompilerOptions::new() };
+	let source = with_std("script f {\n\tu8 a = 1;\n\tu16 b = 2;\n}\n#asm\n\tld a, f@b\n#end\n");
+	let asm = compile_with(&source, options).unwrap_or_else(|msg| panic!("Compilation failed: {msg}"));
+	let body = function(&asm, "f");
+	let equate = body.iter().position(|line| line.starts_with("redef f@b = ")).unwrap();
+	let reference = body.iter().position(|line| line == "ld a, f@b").unwrap();
+	assert!(equate < reference);
+	assert!(body.contains(&String::from("redef f@a = 0")));
+	// Without the option, no equates are defined.
+	assert!(!compile(&source).contains("redef"));
+}
+
+#[test]
+fn each_dereference_peels_one_pointer() {
+	let asm = compile(&with_std("script f {\n\tu16 address = 0xC000;\n\tu8 ptr ptr pp = address;\n\tu8 x = [[pp]];\n}\n"));
+	let derefs = function(&asm, "f").into_iter()
+		.filter_map(|line| line.strip_prefix("db script@deref_").map(|line| String::from(line.split(',').next().unwrap())))
+		.collect::<Vec<_>>();
+	assert_eq!(derefs, ["u16", "u8"]);
+
+	assert_eq!(
+		compile_error(&with_std("script f {\n\tu8 x = 1;\n\tu8 y = [x];\n}\n")),
+		"Attempting to deref a non-pointer! Note that address-of returns a `u16`, not a `u16 ptr`. Try declaring the pointer before dereferencing.",
+	);
+}
+
+#[test]
+fn null_is_the_environments_sentinel() {
+	let source = "env vm {\n\tuse std;\n\tnull = $FFFF;\n\tpool = 16;\n}\nvm f {\n\tu8 ptr p = null;\n\tu8 is_null = p == null;\n}\n";
+	assert!(function(&compile(&with_std(source)), "f").iter().any(|line| line.starts_with("db vm@equ_u16")));
+	let run = run_f(source);
+	assert_eq!(run.word("p"), 0xFFFF);
+	assert_eq!(run.byte("is_null"), 1);
+	// Without a `null` statement, null is 0.
+	assert_eq!(run_f("script f {\n\tu8 ptr p = null;\n}\n").word("p"), 0);
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use common::*;
+use evscript::backend::{EmitBackend, Op};
+use evscript::Compiler;
+use std::io;
+
+/// Counts each kind of piece it is handed, and the opcodes of the instructions.
+#[derive(Default)]
+struct Counter {
+	opcodes: Vec<String>,
+	macros: Vec<String>,
+	labels: usize,
+	data: usize,
+}
+
+impl EmitBackend for Counter {
+	fn instruction(&mut self, opcode: &str, _operands: &[&str]) -> io::Result<()> {
+		self.opcodes.push(String::from(opcode));
+		Ok(())
+	}
+
+	fn macro_invocation(&mut self, name: &str, _args: &[&str]) -> io::Result<()> {
+		self.macros.push(String::from(name));
+		Ok(())
+	}
+
+	fn label(&mut self, _name: &str) -> io::Result<()> {
+		self.labels += 1;
+		Ok(())
+	}
+
+	fn data(&mut self, _size: u8, values: &[&str]) -> io::Result<()> {
+		self.data += values.len();
+		Ok(())
+	}
+
+	fn directive(&mut self, _line: &str) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+#[test]
+fn backends_receive_each_instruction() {
+	let mut counter = Counter::default();
+	let compilation = Compiler::new("test.evs")
+		.source(&with_std("script f {\n\tu8 a = 1;\n\tu8 b = 2;\n\tdata [1, 2, 3];\n}\n"))
+		.compile_with(&mut counter);
+	assert!(compilation.result.is_ok());
+	assert_eq!(counter.opcodes, ["script@put_u8", "script@put_u8", "0"]);
+	// `f::`
+	assert_eq!(counter.labels, 1);
+	assert_eq!(counter.data, 3);
+}
+
+#[test]
+fn backends_receive_macros_separately() {
+	let mut counter = Counter::default();
+	let compilation = Compiler::new("test.evs")
+		.source(&with_std("env fx {\n\tuse script;\n\tmacro shake(u8) = do_shake;\n\tpool = 16;\n}\nfx f {\n\tshake(1);\n}\n"))
+		.compile_with(&mut counter);
+	assert!(compilation.result.is_ok());
+	assert_eq!(counter.macros, ["do_shake"]);
+}
+
+#[test]
+fn recorded_pieces_know_their_size() {
+	let mut ops = Vec::<Op>::new();
+	let compilation = Compiler::new("test.evs")
+		.source(&with_std("script f {\n\tu16 s = \"hi\";\n}\n"))
+		.compile_with(&mut ops);
+	assert!(compilation.result.is_ok());
+	let start = ops.iter().position(|op| *op == Op::Label(String::from("f::"))).unwrap();
+	// Two 3-byte puts, the terminator, and "hi" with its terminator.
+	assert_eq!(ops[start..].iter().map(|op| op.size().unwrap()).sum::<usize>(), 3 + 3 + 1 + 3);
+}
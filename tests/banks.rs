@@ -0,0 +1,40 @@
+mod common;
+
+use common::*;
+
+fn call(asm: &str, caller: &str) -> String {
+	function(asm, caller)
+		.into_iter()
+		.find(|line| line.contains("call"))
+		.expect("the caller contains a call")
+}
+
+#[test]
+fn calls_between_unbanked_functions_are_far() {
+	let asm = compile(&with_std("script f {\n\tg();\n}\nscript g {\n}\n"));
+	assert!(call(&asm, "f").contains("farcall"), "{}", call(&asm, "f"));
+}
+
+#[test]
+fn calls_within_a_bank_are_near() {
+	let asm = compile(&with_std("@bank(2)\nscript f {\n\tg();\n}\n@bank(2)\nscript g {\n}\n"));
+	assert!(!call(&asm, "f").contains("farcall"), "{}", call(&asm, "f"));
+}
+
+#[test]
+fn calls_between_banks_are_far() {
+	let asm = compile(&with_std("@bank(2)\nscript f {\n\tg();\n}\n@bank(3)\nscript g {\n}\n"));
+	assert!(call(&asm, "f").contains("farcall"), "{}", call(&asm, "f"));
+}
+
+#[test]
+fn falling_through_into_another_bank_is_an_error() {
+	let msg = compile_error(&with_std("@falls_through\nscript f {\n}\n@bank(2)\nscript g {\n}\n"));
+	assert_eq!(msg, "g is placed in bank 2, but f falls through into it from another section");
+}
+
+#[test]
+fn falling_through_within_a_bank_is_allowed() {
+	let asm = compile(&with_std("@bank(2) @falls_through\nscript f {\n}\n@bank(2)\nscript g {\n}\n"));
+	assert!(!asm.contains("section \"g evscript fn\""));
+}
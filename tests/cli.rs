@@ -0,0 +1,137 @@
+mod common;
+
+use common::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+/// Writes `source` to a file in a directory of its own, returning the directory.
+fn input(test: &str, source: &str) -> PathBuf {
+	let dir = std::env::temp_dir().join(format!("evscript-{test}-{}", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	fs::write(dir.join("input.evs"), source).unwrap();
+	dir
+}
+
+/// Runs evscript on `dir`'s input with `args`.
+fn evscript(dir: &Path, args: &[&str]) -> Output {
+	Command::new(env!("CARGO_BIN_EXE_evscript"))
+		.args(args)
+		.arg(dir.join("input.evs"))
+		.output()
+		.unwrap()
+}
+
+#[test]
+fn dry_runs_write_nothing_and_report_the_size() {
+	let dir = input("dry-run", &with_std("script f {\n\tu8 a = 1;\n}\n"));
+	let output = dir.join("output.asm");
+	let result = evscript(&dir, &["--dry-run", "-o", output.to_str().unwrap()]);
+	assert!(result.status.success());
+	assert!(!output.exists());
+	// `put_u8` and its two operands, then the terminator.
+	let stderr = String::from_utf8(result.stderr).unwrap();
+	assert!(stderr.contains("1 functions (4 bytes), 0 data sections (0 bytes), 4 bytes total"), "{stderr}");
+	fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn parse_only_reports_syntax_errors_without_compiling() {
+	let dir = input("parse-only-invalid", "script f {\n\tu8 a = ;\n}\n");
+	let result = evscript(&dir, &["--parse-only"]);
+	assert!(!result.status.success());
+	assert!(String::from_utf8(result.stderr).unwrap().contains("Unexepected token. Got \";\""));
+	fs::remove_dir_all(dir).unwrap();
+
+	// Neither the environment nor the variable exist, but only the syntax is checked.
+	let dir = input("parse-only-valid", "nowhere f {\n\tu8 a = b;\n}\n");
+	let result = evscript(&dir, &["--parse-only"]);
+	assert!(result.status.success());
+	assert!(result.stdout.is_empty() && result.stderr.is_empty());
+	fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn colors_reach_diagnostics_but_never_the_output() {
+	let dir = input("color", &with_std("script f {\n\tu8 a = 1;\n\ta + 1;\n}\n"));
+	let result = evscript(&dir, &["--color", "always", "-o", "-"]);
+	assert!(result.status.success());
+	let stdout = String::from_utf8(result.stdout).unwrap();
+	let stderr = String::from_utf8(result.stderr).unwrap();
+	assert!(stdout.contains("db script@put_u8, 0, 1"));
+	assert!(!stdout.contains('\x1b'));
+	assert!(stderr.contains("the result of this expression is unused"));
+	assert!(stderr.contains('\x1b'));
+	fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn quiet_hides_warnings_but_not_errors() {
+	let dir = input("quiet-warning", &with_std("script f {\n\tu8 a = 1;\n\ta + 1;\n}\n"));
+	let result = evscript(&dir, &["--quiet", "-o", "-"]);
+	assert!(result.status.success());
+	assert!(result.stderr.is_empty());
+	fs::remove_dir_all(dir).unwrap();
+
+	let dir = input("quiet-error", &with_std("script f {\n\tmissing();\n}\n"));
+	let result = evscript(&dir, &["--quiet", "-o", "-"]);
+	assert!(!result.status.success());
+	assert!(String::from_utf8(result.stderr).unwrap().contains("Definition of missing not found"));
+	fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn dependency_files_list_every_included_file() {
+	let dir = input("dep-file", "include \"inc.evs\";\n");
+	fs::write(dir.join("inc.evs"), "include \"inc2.evs\";\n").unwrap();
+	fs::write(dir.join("inc2.evs"), "").unwrap();
+	let path = |name: &str| String::from(dir.join(name).to_str().unwrap());
+
+	let result = evscript(&dir, &["-o", &path("out.asm"), "--dep-file", &path("out.d")]);
+	assert!(result.status.success());
+	assert_eq!(
+		fs::read_to_string(dir.join("out.d")).unwrap(),
+		format!("{}: {} {} {}\n", path("out.asm"), path("input.evs"), path("inc.evs"), path("inc2.evs")),
+	);
+	fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn includes_are_searched_next_to_their_file_then_in_include_directories() {
+	let dir = input("include-paths", "include \"inc.evs\";\n");
+	fs::create_dir_all(dir.join("lib")).unwrap();
+	fs::write(dir.join("inc.evs"), "include \"vm.evs\";\n").unwrap();
+	fs::write(dir.join("lib/vm.evs"), "env vm {\n\tdef wait();\n\tpool = 16;\n}\n").unwrap();
+	fs::write(dir.join("lib/inc.evs"), "This file is not the one next to input.evs").unwrap();
+	let path = |name: &str| String::from(dir.join(name).to_str().unwrap());
+
+	let result = evscript(&dir, &["-I", &path("lib"), "-o", "-"]);
+	assert!(result.status.success(), "{}", String::from_utf8_lossy(&result.stderr));
+	assert!(String::from_utf8(result.stdout).unwrap().contains("def vm@wait equ 0"));
+
+	// Every directory tried is listed when the file isn't found.
+	let result = evscript(&dir, &["-o", "-"]);
+	assert!(!result.status.success());
+	let stderr = String::from_utf8(result.stderr).unwrap();
+	assert!(stderr.contains(&format!("vm.evs: file not found; tried {}, vm.evs", path("vm.evs"))), "{stderr}");
+	fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn scripts_may_be_read_from_standard_input() {
+	let mut child = Command::new(env!("CARGO_BIN_EXE_evscript"))
+		.args(["-o", "-", "-"])
+		.current_dir(env!("CARGO_MANIFEST_DIR"))
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()
+		.unwrap();
+	// Includes are relative to the current directory.
+	let source = "include \"scripts/std.evs\";\nenv script {\n\tuse std;\n\tpool = 16;\n}\nscript f {\n\tu8 a = 1;\n}\n";
+	child.stdin.take().unwrap().write_all(source.as_bytes()).unwrap();
+	let result = child.wait_with_output().unwrap();
+	assert!(result.status.success(), "{}", String::from_utf8_lossy(&result.stderr));
+	assert!(String::from_utf8(result.stdout).unwrap().contains("db script@put_u8, 0, 1"));
+}
@@ -0,0 +1,212 @@
+#![allow(dead_code)]
+
+use evscript::compiler::CompilerOptions;
+use evscript::Compiler;
+
+/// Prefixes `source` with an environment named `script` which uses std.
+pub fn with_std(source: &str) -> String {
+	format!(
+		"include \"{}/scripts/std.evs\";\nenv script {{\n\tuse std;\n\tpool = 16;\n}}\n{source}",
+		env!("CARGO_MANIFEST_DIR"),
+	)
+}
+
+/// Compiles `source`, returning the assembly or the message of the first error.
+pub fn compile_with(source: &str, options: CompilerOptions) -> Result<String, String> {
+	let (output, compilation) = Compiler::new("test.evs").source(source).options(options).compile_to_string();
+	output.ok_or_else(|| compilation.result.err().into_iter().flatten().map(|err| err.msg).next().unwrap_or_default())
+}
+
+/// Compiles `source`, which must succeed, returning the message of each warning.
+pub fn warnings(source: &str) -> Vec<String> {
+	let (_, compilation) = Compiler::new("test.evs").source(source).compile_to_string();
+	if let Err(errors) = compilation.result {
+		panic!("Compilation failed: {}", errors[0].msg);
+	}
+	compilation.warnings.into_iter().map(|warning| warning.msg).collect()
+}
+
+pub fn compile(source: &str) -> String {
+	compile_with(source, CompilerOptions::new()).unwrap_or_else(|msg| panic!("Compilation failed: {msg}"))
+}
+
+pub fn compile_error(source: &str) -> String {
+	match compile_with(source, CompilerOptions::new()) {
+		Ok(..) => panic!("Compilation succeeded"),
+		Err(msg) => msg,
+	}
+}
+
+/// Returns the lines of a function's bytecode, from its label to the end of its section, without indentation.
+pub fn function(asm: &str, name: &str) -> Vec<String> {
+	asm.lines()
+		.skip_while(|line| !line.starts_with(&format!("{name}:")))
+		.skip(1)
+		.take_while(|line| !line.starts_with("section"))
+		.map(|line| String::from(line.trim()))
+		.filter(|line| !line.is_empty())
+		.collect()
+}
+
+/// Evaluates an operand of a `db`, which is a sum of numbers, optionally wrapped in LOW() or HIGH(),
+/// or a number masked with `& $FF` or shifted with `>> 8`.
+fn operand(text: &str) -> i64 {
+	let text = text.trim();
+	if let Some((value, mask)) = text.split_once(" & ") {
+		return operand(value) & operand(mask);
+	}
+	if let Some((value, shift)) = text.split_once(" >> ") {
+		return operand(value) >> operand(shift);
+	}
+	if let Some(inner) = text.strip_prefix("LOW(").and_then(|t| t.strip_suffix(')')) {
+		return operand(inner) & 0xFF;
+	}
+	if let Some(inner) = text.strip_prefix("HIGH(").and_then(|t| t.strip_suffix(')')) {
+		return (operand(inner) >> 8) & 0xFF;
+	}
+	let mut total = 0;
+	let mut sign = 1;
+	for term in text.split(' ') {
+		match term {
+			"+" => sign = 1,
+			"-" => sign = -1,
+			term => {
+				let value = match term.strip_prefix('$') {
+					Some(hex) => i64::from_str_radix(hex, 16).unwrap(),
+					None => term.parse::<i64>().unwrap_or_else(|_| panic!("Unknown operand {text}")),
+				};
+				total += sign * value;
+			}
+		}
+	}
+	total
+}
+
+/// Runs a function's bytecode as std's opcodes are meant to behave, returning the pool afterwards.
+/// Only the opcodes which take no arguments beyond their slots are understood, and values are little-endian.
+pub fn run(asm: &str, name: &str) -> [u8; 256] {
+	let lines = function(asm, name);
+	let labels = lines.iter()
+		.enumerate()
+		.filter(|(_, line)| line.starts_with('.'))
+		.map(|(i, line)| (line.clone(), i))
+		.collect::<std::collections::HashMap<_, _>>();
+	let mut pool = [0u8; 256];
+	let mut pc = 0;
+
+	let read = |pool: &[u8; 256], slot: i64, size: u8, signed: bool| -> i64 {
+		let slot = slot as usize;
+		match (size, signed) {
+			(1, false) => pool[slot] as i64,
+			(1, true) => pool[slot] as i8 as i64,
+			(_, false) => u16::from_le_bytes([pool[slot], pool[slot + 1]]) as i64,
+			(_, true) => i16::from_le_bytes([pool[slot], pool[slot + 1]]) as i64,
+		}
+	};
+	let write = |pool: &mut [u8; 256], slot: i64, size: u8, value: i64| {
+		let slot = slot as usize;
+		pool[slot] = value as u8;
+		if size == 2 {
+			pool[slot + 1] = (value >> 8) as u8;
+		}
+	};
+
+	while pc < lines.len() {
+		let line = &lines[pc];
+		pc += 1;
+		let Some(instruction) = line.strip_prefix("db ") else { continue };
+		if instruction == "0" {
+			break;
+		}
+		let (opcode, operands) = instruction.split_once(", ").unwrap_or((instruction, ""));
+		let opcode = opcode.rsplit('@').next().unwrap();
+		let operands = operands.split(", ").collect::<Vec<_>>();
+		let label = |operands: &[&str]| {
+			let target = operands[0].strip_prefix("LOW(").and_then(|t| t.strip_suffix(')')).unwrap();
+			labels[target]
+		};
+
+		let (op, t) = opcode.rsplit_once('_').unwrap_or((opcode, ""));
+		let (signed, size) = match t {
+			"u8" => (false, 1),
+			"i8" => (true, 1),
+			"u16" => (false, 2),
+			"i16" => (true, 2),
+			_ => (false, 1),
+		};
+		match (op, opcode) {
+			(_, "jmp") => pc = label(&operands),
+			(_, "jmp_if_false") => if pool[operand(operands[0]) as usize] == 0 { pc = label(&operands[1..]) },
+			(_, "jmp_if_true") => if pool[operand(operands[0]) as usize] != 0 { pc = label(&operands[1..]) },
+			("put", _) => {
+				let value = operands[1..].iter().rev().fold(0, |value, byte| value << 8 | (operand(byte) & 0xFF));
+				write(&mut pool, operand(operands[0]), size, value);
+			}
+			("mov", _) => {
+				let value = read(&pool, operand(operands[1]), size, false);
+				write(&mut pool, operand(operands[0]), size, value);
+			}
+			(op, _) => {
+				let l = read(&pool, operand(operands[0]), size, signed);
+				let r = read(&pool, operand(operands[1]), size, signed);
+				let (result, result_size) = match op {
+					"add" => (l + r, size),
+					"sub" => (l - r, size),
+					"mul" => (l * r, size),
+					"div" => (l / r, size),
+					"mod" => (l % r, size),
+					"band" => (l & r, size),
+					"bor" => (l | r, size),
+					"bxor" => (l ^ r, size),
+					"shl" => (l << (r & 63), size),
+					"shr" => (l >> (r & 63), size),
+					"equ" => ((l == r) as i64, 1),
+					"nequ" => ((l != r) as i64, 1),
+					"lt" => ((l < r) as i64, 1),
+					"gt" => ((l > r) as i64, 1),
+					"lte" => ((l <= r) as i64, 1),
+					"gte" => ((l >= r) as i64, 1),
+					"land" => ((l != 0 && r != 0) as i64, 1),
+					"lor" => ((l != 0 || r != 0) as i64, 1),
+					_ => panic!("Unknown opcode {opcode}"),
+				};
+				write(&mut pool, operand(operands[2]), result_size, result);
+			}
+		}
+	}
+
+	pool
+}
+
+/// The pool after running a function, with the slot of each of its variables.
+pub struct Run {
+	pub pool: [u8; 256],
+	slots: std::collections::HashMap<String, usize>,
+}
+
+impl Run {
+	pub fn byte(&self, variable: &str) -> u8 {
+		self.pool[self.slots[variable]]
+	}
+
+	pub fn word(&self, variable: &str) -> u16 {
+		let slot = self.slots[variable];
+		u16::from_le_bytes([self.pool[slot], self.pool[slot + 1]])
+	}
+}
+
+/// Compiles `source` with std and runs its function `f`.
+pub fn run_f(source: &str) -> Run {
+	run_f_with(source, CompilerOptions::new())
+}
+
+pub fn run_f_with(source: &str, mut options: CompilerOptions) -> Run {
+	options.variable_equates = true;
+	let asm = compile_with(&with_std(source), options).unwrap_or_else(|msg| panic!("Compilation failed: {msg}"));
+	let slots = function(&asm, "f").iter()
+		.filter_map(|line| line.strip_prefix("redef f@"))
+		.filter_map(|line| line.split_once(" = "))
+		.map(|(name, slot)| (String::from(name), slot.parse().unwrap()))
+		.collect();
+	Run { pool: run(&asm, "f"), slots }
+}
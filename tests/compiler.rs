@@ -0,0 +1,56 @@
+mod common;
+
+use common::*;
+use evscript::Compiler;
+
+#[test]
+fn compilers_return_the_output_and_diagnostics() {
+	let source = with_std("script f {\n\tu8 a = 1;\n\ta + 1;\n}\n");
+	let mut output = Vec::new();
+	let compilation = Compiler::new("test.evs").reader(source.as_bytes()).unwrap().compile_to(&mut output);
+
+	let summary = compilation.result.unwrap_or_else(|errors| panic!("Compilation failed: {}", errors[0].msg));
+	assert_eq!(summary.functions, 1);
+	let asm = String::from_utf8(output).unwrap();
+	assert_eq!(function(&asm, "f")[0], "db script@put_u8, 0, 1");
+	let warnings = compilation.warnings.into_iter().map(|warning| warning.msg).collect::<Vec<_>>();
+	assert_eq!(warnings, ["the result of this expression is unused"]);
+}
+
+#[test]
+fn compilers_read_their_path_without_a_source() {
+	let path = concat!(env!("CARGO_MANIFEST_DIR"), "/scripts/std.evs");
+	let (_, compilation) = Compiler::new(path).compile_to_string();
+	assert!(compilation.result.is_ok());
+
+	let (_, compilation) = Compiler::new("missing.evs").compile_to_string();
+	let errors = compilation.result.err().unwrap();
+	assert!(errors[0].msg.starts_with("missing.evs: "));
+}
+
+#[test]
+fn compiling_to_a_string_returns_everything() {
+	let source = with_std("script f {\n\tu8 a = 1;\n\tu16 b = 2;\n\ta + 1;\n}\n");
+	let (asm, compilation) = Compiler::new("test.evs").source(&source).compile_to_string();
+	assert!(asm.unwrap().contains("f::"));
+	assert_eq!(compilation.warnings[0].msg, "the result of this expression is unused");
+	let summary = compilation.result.unwrap_or_else(|errors| panic!("Compilation failed: {}", errors[0].msg));
+	// `a` and `b`, with `1` and the sum in temporaries.
+	assert_eq!(summary.peak_usage, [(String::from("f"), 5)]);
+}
+
+#[test]
+fn failed_compilations_return_no_assembly() {
+	let source = with_std("script f {}\nscript g {\n\tu8 a = b;\n}\n");
+	let (asm, compilation) = Compiler::new("test.evs").source(&source).compile_to_string();
+	assert_eq!(asm, None);
+	assert_eq!(compilation.result.err().unwrap()[0].msg, "Variable b does not exist");
+}
+
+#[test]
+fn missing_includes_are_errors_at_their_include() {
+	let source = "include \"missing.evs\";\n";
+	let (_, compilation) = Compiler::new("test.evs").source(source).compile_to_string();
+	let errors = compilation.result.err().unwrap();
+	assert_eq!(&source[errors[0].start.unwrap()..errors[0].end.unwrap()], "include \"missing.evs\";");
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use common::*;
+
+#[test]
+fn function_data_is_placed_after_the_terminator() {
+	let asm = compile(&with_std("script f {\n\tu8 a = 1;\n\tdata tbl [1, 2, 3];\n\tu8 b = 2;\n}\n"));
+	assert_eq!(function(&asm, "f"), [
+		"db script@put_u8, 0, 1",
+		"db script@put_u8, 1, 2",
+		"db 0",
+		".tbl",
+		"db 1, 2, 3",
+	]);
+}
+
+#[test]
+fn function_data_follows_its_strings() {
+	let asm = compile(&with_std("script f {\n\tdata16 [$1234];\n\tu16 s = \"hi\";\n}\n"));
+	let body = function(&asm, "f");
+	let terminator = body.iter().position(|line| line == "db 0").unwrap();
+	assert_eq!(&body[terminator..], ["db 0", ".__string0", "db \"hi\", 0", "dw 4660"]);
+}
+
+#[test]
+fn functions_which_fall_through_may_not_contain_data() {
+	let msg = compile_error(&with_std("@falls_through\nscript f {\n\tdata [1];\n}\nscript g {\n}\n"));
+	assert_eq!(msg, "f falls through and may not contain strings or data");
+}
+
+#[test]
+fn data_in_duplicated_repeats_is_placed_once() {
+	let asm = compile(&with_std("script f {\n\tu8 a = 0;\n\trepeat 3 {\n\t\ta += 1;\n\t\tdata tbl [1];\n\t}\n\t@unroll(2) repeat 5 {\n\t\tdata16 wide [2];\n\t}\n}\n"));
+	let body = function(&asm, "f");
+	assert_eq!(body.iter().filter(|line| *line == ".tbl").count(), 1);
+	assert_eq!(body.iter().filter(|line| *line == ".wide").count(), 1);
+}
+
+#[test]
+fn data_in_loops_which_never_run_is_kept() {
+	let asm = compile(&with_std("script f {\n\trepeat 0 {\n\t\tdata tbl [1];\n\t}\n}\n"));
+	assert!(function(&asm, "f").contains(&String::from(".tbl")));
+}
+
+#[test]
+fn tables_of_function_addresses_are_words() {
+	// `b` is only defined after the table, which the linker resolves.
+	let asm = compile(&with_std("script a {}\ndata16 table [&a, &b];\nscript b {}\n"));
+	assert_eq!(function(&asm, "table"), ["dw a, b"]);
+}
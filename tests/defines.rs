@@ -0,0 +1,65 @@
+mod common;
+
+use common::*;
+use evscript::compiler::CompilerOptions;
+
+fn with_n(value: i64) -> CompilerOptions {
+	let mut options = CompilerOptions::new();
+	options.defines.insert(String::from("N"), value);
+	options
+}
+
+#[test]
+fn definitions_initialize_variables() {
+	let run = run_f_with("script f {\n\tu8 a = N;\n\tu16 b = N + 1;\n}\n", with_n(2));
+	assert_eq!(run.byte("a"), 2);
+	assert_eq!(run.word("b"), 3);
+}
+
+#[test]
+fn variables_shadow_definitions() {
+	let run = run_f_with("script f {\n\tu8 N = 5;\n\tu8 a = N;\n}\n", with_n(2));
+	assert_eq!(run.byte("a"), 5);
+}
+
+#[test]
+fn undefined_names_are_still_errors() {
+	let msg = compile_with(&with_std("script f {\n\tu8 a = M;\n}\n"), with_n(2)).unwrap_err();
+	assert!(msg.contains("M does not exist"), "{msg}");
+}
+
+#[test]
+fn not_of_a_definition_is_bitwise() {
+	let mut options = with_n(0);
+	options.optimize = true;
+	let asm = compile_with(&with_std("script f {\n\tif !N {\n\t\tyld();\n\t}\n}\n"), options.clone()).unwrap();
+	assert_eq!(function(&asm, "f"), ["db script@yld", "db 0"]);
+
+	// Only a value with every bit set becomes 0.
+	options.defines.insert(String::from("N"), -1);
+	let asm = compile_with(&with_std("script f {\n\tif !N {\n\t\tyld();\n\t}\n}\n"), options).unwrap();
+	assert_eq!(function(&asm, "f"), ["db 0"]);
+}
+
+#[test]
+fn negated_definitions_are_negative() {
+	assert!(compile_with(&with_std("static_assert(-N < 0);\nstatic_assert(-N == 0 - 2);\n"), with_n(2)).is_ok());
+	let msg = compile_with(&with_std("static_assert(-N > 0, \"N is negated\");\n"), with_n(2)).unwrap_err();
+	assert_eq!(msg, "N is negated");
+}
+
+#[test]
+fn dividing_by_a_zero_definition_is_an_error() {
+	let msg = compile_with(&with_std("script f {\n\tu8 a = 4 / N;\n}\n"), with_n(0)).unwrap_err();
+	assert_eq!(msg, "Division by zero");
+	let msg = compile_with(&with_std("script f {\n\tu8 a = 4 % N;\n}\n"), with_n(0)).unwrap_err();
+	assert_eq!(msg, "Division by zero");
+}
+
+#[test]
+fn shifting_by_a_wide_definition_is_an_error() {
+	let msg = compile_with(&with_std("script f {\n\tu8 a = 4 << N;\n}\n"), with_n(64)).unwrap_err();
+	assert_eq!(msg, "Shift amount out of range");
+	let msg = compile_with(&with_std("static_assert(4 >> N == 0);\n"), with_n(-1)).unwrap_err();
+	assert_eq!(msg, "Shift amount out of range");
+}
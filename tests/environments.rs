@@ -0,0 +1,100 @@
+mod common;
+
+use common::*;
+use evscript::compiler::{CompilerOptions, MacroCheck};
+use evscript::Compiler;
+
+#[test]
+fn dispatch_tables_list_each_bytecode_in_order() {
+	// Replacing `x` with an alias leaves its bytecode without a definition.
+	let source = "env a {\n\tdef x();\n\tdef y();\n\tdef z();\n}\nenv vm {\n\tuse a;\n\talias x() = y();\n\tpool = 16;\n}\n";
+	let options = CompilerOptions { dispatch_environment: Some(String::from("vm")), ..CompilerOptions::new() };
+	let asm = compile_with(source, options).unwrap_or_else(|msg| panic!("Compilation failed: {msg}"));
+	assert_eq!(function(&asm, "evs_vm_dispatch"), ["dw evs_unimplemented", "dw evs_vm_y", "dw evs_vm_z"]);
+}
+
+#[test]
+fn required_environment_versions_are_checked() {
+	let source = "env vm {\n\tversion 2;\n\tpool = 16;\n}\nenv other {\n\tpool = 16;\n}\n";
+	let require = |env: &str, version| CompilerOptions {
+		required_versions: vec![(String::from(env), version)],
+		..CompilerOptions::new()
+	};
+	assert!(compile_with(source, require("vm", 2)).is_ok());
+	assert_eq!(
+		compile_with(source, require("vm", 3)),
+		Err(String::from("Environment vm is version 2, but version 3 is required")),
+	);
+	assert_eq!(
+		compile_with(source, require("other", 1)),
+		Err(String::from("Environment other has no version, but version 1 is required")),
+	);
+}
+
+#[test]
+fn aliased_bytecodes_are_named_after_their_definition() {
+	let source = "env vm {\n\tdef add_u8();\n\talias add_i8() = add_u8();\n\tdef sub_u8();\n\tpool = 16;\n}\n";
+	let options = CompilerOptions { dispatch_environment: Some(String::from("vm")), ..CompilerOptions::new() };
+	let asm = compile_with(source, options).unwrap_or_else(|msg| panic!("Compilation failed: {msg}"));
+	assert_eq!(function(&asm, "evs_vm_dispatch"), ["dw evs_vm_add_u8", "dw evs_vm_sub_u8"]);
+}
+
+const FEATURES: &str = "env vm {\n\tdef wait();\n\tfeature debug {\n\t\tdef print();\n\t}\n\tdef stop();\n\tpool = 16;\n}\nvm f {\n\tprint();\n}\n";
+
+#[test]
+fn feature_definitions_exist_only_with_their_feature() {
+	assert_eq!(
+		compile_with(FEATURES, CompilerOptions::new()),
+		Err(String::from("print is only available with the debug feature enabled")),
+	);
+	let asm = compile_with(FEATURES.replace("\tprint();\n", "\tstop();\n").as_str(), CompilerOptions::new()).unwrap();
+	// Bytecodes are dense without the feature's definitions.
+	assert!(asm.contains("def vm@stop equ 1"));
+
+	let options = CompilerOptions { features: vec![String::from("debug")], ..CompilerOptions::new() };
+	let asm = compile_with(FEATURES, options).unwrap_or_else(|msg| panic!("Compilation failed: {msg}"));
+	assert!(asm.contains("def vm@print equ 1"));
+	assert!(asm.contains("def vm@stop equ 2"));
+	assert_eq!(function(&asm, "f"), ["db vm@print", "db 0"]);
+}
+
+#[test]
+fn alias_arguments_are_checked_where_the_alias_is_defined() {
+	let error = |alias: &str| {
+		let source = format!("env vm {{\n\tdef wait(u8, u8);\n\t{alias};\n\tpool = 16;\n}}\n");
+		let (_, compilation) = Compiler::new("test.evs").source(&source).compile_to_string();
+		let error = compilation.result.err().unwrap().remove(0);
+		assert_eq!(&source[error.start.unwrap()..error.end.unwrap()], alias);
+		error.msg
+	};
+	assert_eq!(error("alias pause(u8, u8) = wait($1, $3)"), "The arguments of pause are numbered $1 to $2, so there is no $3");
+	assert_eq!(error("alias pause(u8) = wait($0, $1)"), "The arguments of pause are numbered $1 to $1, so there is no $0");
+	assert_eq!(error("alias pause() = wait($1, 2)"), "pause takes no arguments, so there is no $1");
+}
+
+#[test]
+fn alias_cycles_and_undefined_targets_are_rejected() {
+	let error = |aliases: &str| {
+		compile_with(&format!("env vm {{\n\tdef wait();\n{aliases}\tpool = 16;\n}}\n"), CompilerOptions::new()).unwrap_err()
+	};
+	// The cycle is named from whichever alias is checked first.
+	let msg = error("\talias a() = b();\n\talias b() = a();\n");
+	assert!(msg == "Alias cycle: a -> b -> a" || msg == "Alias cycle: b -> a -> b", "{msg}");
+	assert_eq!(error("\talias a() = a();\n"), "Alias cycle: a -> a");
+	assert_eq!(error("\talias a() = missing();\n"), "a is an alias of missing, which is not defined");
+}
+
+#[test]
+fn macro_targets_are_asserted_or_listed() {
+	let source = with_std("env fx {\n\tuse script;\n\tmacro shake(u8) = do_shake;\n\tmacro flash() = do_flash;\n\tpool = 16;\n}\nfx f {\n\tshake(2);\n}\n");
+	let checked = |check| CompilerOptions { check_macros: Some(check), ..CompilerOptions::new() };
+
+	let asm = compile_with(&source, checked(MacroCheck::Assert)).unwrap_or_else(|msg| panic!("Compilation failed: {msg}"));
+	assert!(asm.contains("assert DEF(do_shake), \"Macro do_shake is not defined\""));
+	assert!(!asm.contains("DEF(do_flash)"));
+
+	// Only the targets of macros which are used are listed.
+	let (_, compilation) = Compiler::new("test.evs").source(&source).options(checked(MacroCheck::Warn)).compile_to_string();
+	let warnings = compilation.warnings.into_iter().map(|warning| warning.msg).collect::<Vec<_>>();
+	assert_eq!(warnings, ["These macros must be defined by the assembler: do_shake"]);
+}
@@ -0,0 +1,132 @@
+mod common;
+
+use common::*;
+use evscript::Compiler;
+
+#[test]
+fn conditional_operator_branches_on_its_condition() {
+	let asm = compile(&with_std("script f {\n\tu8 a = 1;\n\tu8 b = a ? 2 : 3;\n}\n"));
+	assert_eq!(function(&asm, "f"), [
+		"db script@put_u8, 0, 1",
+		"db script@jmp_if_false, 0, LOW(.__select_else0), HIGH(.__select_else0)",
+		"db script@put_u8, 1, 2",
+		"db script@mov_u8, 3, 1",
+		"db script@jmp, LOW(.__select_end0), HIGH(.__select_end0)",
+		".__select_else0",
+		"db script@put_u8, 2, 3",
+		"db script@mov_u8, 3, 2",
+		".__select_end0",
+		"db 0",
+	]);
+}
+
+#[test]
+fn conditional_operator_with_constant_condition_is_folded() {
+	let asm = compile(&with_std("script f {\n\tu8 a = 1 ? 4 : 5;\n\tu8 b = 0 ? 4 : 5;\n}\n"));
+	assert_eq!(function(&asm, "f"), [
+		"db script@put_u8, 0, 4",
+		"db script@put_u8, 1, 5",
+		"db 0",
+	]);
+}
+
+#[test]
+fn conditional_operator_nests_to_the_right() {
+	let asm = compile(&with_std("script f {\n\tu8 a = 1;\n\tu8 b = a ? 1 : a ? 2 : 3;\n}\n"));
+	let body = function(&asm, "f");
+	assert!(body.contains(&String::from(".__select_end0")));
+	assert!(body.contains(&String::from(".__select_end1")));
+}
+
+#[test]
+fn signed_16_bit_comparisons_are_signed() {
+	let source = "script f {\n\ti16 a = -300;\n\ti16 b = 2;\n\tu8 lt = a < b;\n\tu8 gte = a >= b;\n}\n";
+	let asm = compile(&with_std(source));
+	assert!(function(&asm, "f").iter().any(|line| line.starts_with("db script@lt_i16")));
+	let run = run_f(source);
+	assert_eq!(run.byte("lt"), 1);
+	assert_eq!(run.byte("gte"), 0);
+}
+
+#[test]
+fn std_defines_every_16_bit_arithmetic_opcode() {
+	let source = "script f {\n\tu16 a = 500;\n\tu16 b = 7;\n\tu16 c = a * b / 3 % 100 << 1 | a ^ b;\n\ti16 d = -500;\n\ti16 e = d / 7 % 3 * 2 << 3 | d ^ 1;\n}\n";
+	let run = run_f(source);
+	assert_eq!(run.word("c"), (((500 * 7 / 3 % 100) << 1) | (500 ^ 7)) as u16);
+	assert_eq!(run.word("e") as i16, ((-500i16 / 7 % 3 * 2) << 3) | (-500 ^ 1));
+}
+
+#[test]
+fn comparisons_of_words_produce_a_byte() {
+	let asm = compile(&with_std("script f {\n\tu16 a = 1;\n\tu16 b = 2;\n\tu8 c = a < b;\n\tu8 d = a && b;\n\tu8 e = 3;\n}\n"));
+	let lines = function(&asm, "f");
+	// `c` fits in the byte freed before `a`, and `e` directly follows `d`.
+	assert_eq!(lines[6..], [
+		"db script@lt_u16, 1, 3, 0",
+		"db script@land_u16, 1, 3, 5",
+		"db script@put_u8, 6, 3",
+		"db 0",
+	]);
+}
+
+#[test]
+fn comparisons_are_widened_when_assigned() {
+	let source = "script f {\n\tu16 x = $FFFF;\n\tu16 y = $FFFF;\n\tu8 z = 7;\n\tu8 a = 1;\n\tu8 b = 2;\n\tif a {\n\t\tu16 junk = $FFFF;\n\t}\n\tx = a < b;\n\ty = z = a > b;\n}\n";
+	let run = run_f(source);
+	assert_eq!(run.word("x"), 1);
+	assert_eq!(run.word("y"), 0);
+	assert_eq!(run.byte("z"), 0);
+}
+
+#[test]
+fn using_the_result_of_a_call_without_one_points_at_the_call() {
+	let source = with_std("script f {\n\tu8 x = 0;\n\tx = g();\n}\nscript g {\n}\n");
+	let (_, compilation) = Compiler::new("test.evs").source(&source).compile_to_string();
+	let errors = compilation.result.expect_err("Compilation succeeded");
+	assert_eq!(errors[0].msg, "g does not return a value, so its result cannot be used");
+	assert_eq!(&source[errors[0].start.unwrap()..errors[0].end.unwrap()], "g()");
+}
+
+#[test]
+fn assigning_to_a_misspelled_variable_suggests_the_closest_one() {
+	let source = with_std("script f {\n\tu8 counter = 0;\n\tu8 colour = 0;\n\tcountr = 1;\n}\n");
+	let (_, compilation) = Compiler::new("test.evs").source(&source).compile_to_string();
+	let errors = compilation.result.expect_err("Compilation succeeded");
+	assert_eq!(errors[0].msg, "Variable countr does not exist; did you mean counter?");
+	assert_eq!(&source[errors[0].start.unwrap()..errors[0].end.unwrap()], "countr");
+}
+
+#[test]
+fn chained_comparisons_are_a_conjunction() {
+	let chain = |x: u8| format!("script f {{\n\tu8 x = {x};\n\tu8 a = 1 < x + 1 < 10;\n}}\n");
+	let body = function(&compile(&with_std(&chain(5))), "f");
+	// The middle operand is computed once, and shared by both comparisons.
+	assert_eq!(body.iter().filter(|line| line.contains("add_u8")).count(), 1);
+	assert_eq!(body.iter().filter(|line| line.contains("lt_u8")).count(), 2);
+	assert_eq!(run_f(&chain(5)).byte("a"), 1);
+	assert_eq!(run_f(&chain(0)).byte("a"), 0);
+	assert_eq!(run_f(&chain(9)).byte("a"), 0);
+}
+
+#[test]
+fn chained_comparisons_widen_their_operands() {
+	// The byte after `x` is set, so reading `x` as a word would see it.
+	let chain = |x: u16| format!("script f {{\n\tu8 x = {x};\n\tu8 y = 255;\n\tu8 a = 1 < x < 300;\n}}\n");
+	let body = function(&compile(&with_std(&chain(5))), "f");
+	assert_eq!(body.iter().filter(|line| line.contains("lt_u16")).count(), 2);
+	assert_eq!(run_f(&chain(5)).byte("a"), 1);
+	assert_eq!(run_f(&chain(255)).byte("a"), 1);
+	assert_eq!(run_f(&chain(0)).byte("a"), 0);
+}
+
+#[test]
+fn chained_assignments_compute_their_value_once() {
+	let source = with_std("env vm {\n\tuse std;\n\tdef rand(return u8);\n\tpool = 16;\n}\nvm f {\n\tu8 a;\n\tu8 b;\n\ta = b = rand();\n}\n");
+	let body = function(&compile(&source), "f");
+	assert_eq!(body.iter().filter(|line| line.starts_with("db vm@rand")).count(), 1);
+	// The result is stored into `b`, then `a`, which are slots 1 and 0.
+	let movs = body.iter().filter(|line| line.starts_with("db vm@mov_u8")).collect::<Vec<_>>();
+	assert_eq!(movs.len(), 2);
+	assert!(movs[0].starts_with("db vm@mov_u8, 1, "));
+	assert!(movs[1].starts_with("db vm@mov_u8, 0, "));
+}
@@ -0,0 +1,38 @@
+mod common;
+
+use common::*;
+
+#[test]
+fn fixed_point_multiplication_keeps_the_scale() {
+	let run = run_f("script f {\n\tq8.8 a = 1.5;\n\tq8.8 b = a * 2.0;\n\tq8.8 c = a * -0.5;\n}\n");
+	assert_eq!(run.word("b"), 0x0300);
+	assert_eq!(run.word("c") as i16, -0x00C0);
+}
+
+#[test]
+fn fixed_point_division_keeps_the_fraction() {
+	let run = run_f("script f {\n\tq8.8 a = 1.5;\n\tq8.8 b = a / 2.0;\n\tq8.8 c = 3.0 / a;\n}\n");
+	assert_eq!(run.word("b"), 0x00C0);
+	assert_eq!(run.word("c"), 0x0200);
+}
+
+#[test]
+fn integers_are_converted_to_fixed_point() {
+	let run = run_f("script f {\n\tq8.8 a = 1.5;\n\tu8 one = 1;\n\tq8.8 b = a + one;\n\tq8.8 c = a * 3;\n\tq8.8 d = 2;\n}\n");
+	assert_eq!(run.word("b"), 0x0280);
+	assert_eq!(run.word("c"), 0x0480);
+	assert_eq!(run.word("d"), 0x0200);
+}
+
+#[test]
+fn fixed_point_is_converted_to_its_integer_part() {
+	let run = run_f("script f {\n\tq8.8 a = 2.75;\n\tu8 b = a;\n\tq8.8 c = -1.5;\n\ti16 d = c;\n}\n");
+	assert_eq!(run.byte("b"), 2);
+	assert_eq!(run.word("d") as i16, -2);
+}
+
+#[test]
+fn fixed_point_opcodes_are_defined_by_std() {
+	let asm = compile(&with_std("script f {\n\tq8.8 a = 1.5;\n\tq8.8 b = a * a / a;\n}\n"));
+	assert!(function(&asm, "f").iter().any(|line| line.starts_with("db script@div_i16")));
+}
@@ -0,0 +1,89 @@
+mod common;
+
+use common::*;
+use evscript::compiler::CompilerOptions;
+use evscript::Compiler;
+
+const YIELDING: &str = "f {\n\tu8 a = 1;\n\tu8 b = a;\n\tyield;\n}\n";
+
+/// Compiles `YIELDING` in the environment `vm`, which has yld_ret if `yield_return` is set,
+/// returning the body of the function and the warnings.
+fn compile_yielding(yield_return: bool) -> (Vec<String>, Vec<String>) {
+	let definition = if yield_return { "\tdef yld_ret();\n" } else { "" };
+	let source = with_std(&format!("env vm {{\n\tuse std;\n\tpool = 16;\n{definition}}}\nvm {YIELDING}"));
+	let options = CompilerOptions {
+		line_directives: true,
+		yield_depth_limit: Some(1),
+		..CompilerOptions::new()
+	};
+	let (asm, compilation) = Compiler::new("test.evs").source(&source).options(options).compile_to_string();
+	compilation.result.unwrap_or_else(|errors| panic!("Compilation failed: {}", errors[0].msg));
+	(function(&asm.unwrap(), "f"), compilation.warnings.into_iter().map(|warning| warning.msg).collect())
+}
+
+#[test]
+fn a_final_yield_is_a_yield_return_if_the_environment_has_one() {
+	let (body, warnings) = compile_yielding(true);
+	// It is annotated and checked like any other yield.
+	assert_eq!(body.last().unwrap(), "db vm@yld_ret");
+	assert!(body[body.len() - 2].starts_with("; test.evs:"));
+	assert!(warnings.contains(&String::from("yielding with 2 live variables, more than the limit of 1")));
+}
+
+#[test]
+fn a_final_yield_is_followed_by_the_terminator_otherwise() {
+	let (body, warnings) = compile_yielding(false);
+	assert_eq!(body[body.len() - 2..], ["db vm@yld", "db 0"]);
+	assert!(body[body.len() - 3].starts_with("; test.evs:"));
+	assert!(warnings.contains(&String::from("yielding with 2 live variables, more than the limit of 1")));
+}
+
+#[test]
+fn an_empty_function_is_only_its_terminator() {
+	let source = with_std("script f {}\n");
+	let (asm, compilation) = Compiler::new("test.evs").source(&source).compile_to_string();
+	compilation.result.unwrap_or_else(|errors| panic!("Compilation failed: {}", errors[0].msg));
+	assert!(compilation.warnings.is_empty());
+	assert_eq!(function(&asm.unwrap(), "f"), ["db 0"]);
+}
+
+#[test]
+fn calling_a_function_emits_its_label() {
+	let asm = compile(&with_std("script f {\n\tg();\n}\nscript g {}\n"));
+	assert_eq!(function(&asm, "f"), ["db script@farcall, BANK(g), LOW(g), HIGH(g)", "db 0"]);
+}
+
+#[test]
+fn a_function_that_falls_through_has_no_terminator() {
+	let asm = compile(&with_std("@falls_through\nscript f {\n\tu8 a = 1;\n}\nscript g {}\n"));
+	let body = function(&asm, "f");
+	// `g` directly follows `f`, in the same section.
+	assert_eq!(body[body.len() - 2..], ["g::", "db 0"]);
+	assert_eq!(body.iter().filter(|line| *line == "db 0").count(), 1);
+}
+
+#[test]
+fn functions_without_an_environment_use_the_assumed_one() {
+	let source = with_std("f {\n\tu8 a = 1;\n}\n");
+	let options = CompilerOptions { assumed_environment: Some(String::from("script")), ..CompilerOptions::new() };
+	let asm = compile_with(&source, options).unwrap_or_else(|msg| panic!("Compilation failed: {msg}"));
+	assert_eq!(function(&asm, "f"), ["db script@put_u8, 0, 1", "db 0"]);
+	assert_eq!(
+		compile_with(&source, CompilerOptions::new()),
+		Err(String::from("Function has no environment, and no environment is assumed")),
+	);
+}
+
+#[test]
+fn line_directives_precede_each_statements_bytecode() {
+	let options = CompilerOptions { line_directives: true, ..CompilerOptions::new() };
+	let source = with_std("script f {\n\tu8 a = 1;\n\n\tif a {\n\t\ta = 2;\n\t}\n}\n");
+	let asm = compile_with(&source, options).unwrap_or_else(|msg| panic!("Compilation failed: {msg}"));
+	let body = function(&asm, "f");
+	// The function begins on line 6, after the lines added by with_std.
+	let line = |n: usize| body.iter().position(|line| *line == format!("; test.evs:{n}")).unwrap();
+	assert_eq!(body[line(7) + 1], "db script@put_u8, 0, 1");
+	assert!(body[line(9) + 1].starts_with("db script@jmp_if_false"));
+	// Statements within the `if` are annotated too.
+	assert_eq!(body[line(10) + 1], "db script@put_u8, 1, 2");
+}
@@ -0,0 +1,23 @@
+mod common;
+
+use common::*;
+use evscript::backend::RgbdsBackend;
+use evscript::compiler::{compile_with_header, CompilerOptions};
+
+#[test]
+fn the_header_is_written_apart_from_the_output() {
+	let source = with_std("struct point {\n\tx: u8,\n\ty: u16,\n}\nscript f {\n\tu8 a = 1;\n}\n");
+	let ast = evscript::parse(&source).unwrap();
+	let mut output = RgbdsBackend::new(Vec::new());
+	let mut header = RgbdsBackend::new(Vec::new());
+	compile_with_header(ast, "test.evs", &source, &mut output, &mut header, CompilerOptions::new(), &mut Vec::new())
+		.unwrap_or_else(|err| panic!("Compilation failed: {}", err.msg));
+	let output = String::from_utf8(output.output).unwrap();
+	let header = String::from_utf8(header.output).unwrap();
+
+	assert!(header.contains("def script@put_u8 equ 5\n"));
+	assert!(header.contains("def point@y equ 1\ndef sizeof@point equ 3\n"));
+	assert!(!header.contains("f::"));
+	assert!(!output.contains(" equ "));
+	assert_eq!(function(&output, "f"), ["db script@put_u8, 0, 1", "db 0"]);
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use common::*;
+use evscript::compiler::CompilerOptions;
+use evscript::Compiler;
+
+#[test]
+fn files_are_included_once_however_their_path_is_spelled() {
+	let dir = env!("CARGO_MANIFEST_DIR");
+	let source = format!(
+		"include \"{dir}/scripts/std.evs\";\ninclude \"{dir}/scripts/../scripts/./std.evs\";\nenv script {{\n\tuse std;\n\tpool = 16;\n}}\nscript f {{}}\n"
+	);
+	let asm = compile(&source);
+	assert_eq!(asm.matches("def std@add_u8 equ").count(), 1);
+}
+
+#[test]
+fn preprocessing_inlines_each_include_once() {
+	let dir = std::env::temp_dir().join(format!("evscript-preprocess-{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	std::fs::write(dir.join("vm.evs"), "env vm {\n\tpool = 16;\n}\n").unwrap();
+	let path = dir.join("main.evs");
+	let input = "include \"vm.evs\";\ninclude \"./vm.evs\";\nvm f {}\n";
+
+	let output = evscript::preprocess(path.to_str().unwrap(), input, &[]).unwrap();
+	let vm = dir.join("vm.evs");
+	let vm = vm.to_str().unwrap();
+	assert_eq!(output, format!("// include \"{vm}\"\nenv vm {{\n\tpool = 16;\n}}\n// end of \"{vm}\"\n\nvm f {{}}\n"));
+	std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn includes_nested_past_the_limit_are_reported_with_their_chain() {
+	let dir = std::env::temp_dir().join(format!("evscript-include-depth-{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	std::fs::write(dir.join("a.evs"), "include \"b.evs\";\n").unwrap();
+	std::fs::write(dir.join("b.evs"), "include \"c.evs\";\n").unwrap();
+	std::fs::write(dir.join("c.evs"), "").unwrap();
+	let main = dir.join("main.evs");
+	let main = main.to_str().unwrap();
+
+	let options = |include_depth_limit| CompilerOptions { include_depth_limit, ..CompilerOptions::new() };
+	let (_, compilation) = Compiler::new(main).source("include \"a.evs\";\n").options(options(3)).compile_to_string();
+	compilation.result.unwrap_or_else(|errors| panic!("Compilation failed: {}", errors[0].msg));
+	let (_, compilation) = Compiler::new(main).source("include \"a.evs\";\n").options(options(2)).compile_to_string();
+	let err = compilation.result.err().unwrap().remove(0);
+	let path = |name: &str| String::from(dir.join(name).to_str().unwrap());
+	assert_eq!(err.msg, format!(
+		"Includes are nested more than 2 levels deep: {main} -> {} -> {} -> {}",
+		path("a.evs"),
+		path("b.evs"),
+		path("c.evs"),
+	));
+	// The error is at the include in b.evs, not at the one in the root file which led there.
+	assert_eq!(err.file, Some(path("b.evs")));
+	assert_eq!(err.get_range(), Some(0..16));
+	std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn functions_and_data_with_the_same_name_in_two_files_are_rejected() {
+	let dir = std::env::temp_dir().join(format!("evscript-duplicates-{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	std::fs::write(dir.join("other.evs"), "script f {}\ndata table [1];\n").unwrap();
+	let main = dir.join("main.evs");
+	let main = main.to_str().unwrap();
+	let other = dir.join("other.evs");
+	let other = other.to_str().unwrap();
+
+	let error = |source: &str| {
+		let (_, compilation) = Compiler::new(main)
+			.source(&with_std(&format!("include \"other.evs\";\n{source}")))
+			.compile_to_string();
+		let err = compilation.result.err().unwrap().remove(0);
+		(err.get_range(), err.msg, err.file)
+	};
+	// The root file's functions are known before the included file is compiled, so the latter is the one reported.
+	assert_eq!(error("script f {}\n"), (Some(0..9), format!("Function f is already defined in {main}"), Some(String::from(other))));
+	assert_eq!(
+		error("script table {}\n"),
+		(Some(12..26), format!("table is already defined as a function in {main}"), Some(String::from(other))),
+	);
+	let (_, msg, file) = error("data f [2];\n");
+	assert_eq!(msg, format!("f is already defined as a function in {other}"));
+	assert_eq!(file, None);
+	std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn warnings_in_included_files_keep_their_file_and_span() {
+	let dir = std::env::temp_dir().join(format!("evscript-include-warnings-{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	std::fs::write(dir.join("other.evs"), "script g {\n\t1 + 2;\n}\n").unwrap();
+	let main = dir.join("main.evs");
+	let other = dir.join("other.evs");
+
+	let (_, compilation) = Compiler::new(main.to_str().unwrap())
+		.source(&with_std("include \"other.evs\";\n"))
+		.compile_to_string();
+	assert!(compilation.result.is_ok());
+	let warning = &compilation.warnings[0];
+	assert_eq!(warning.msg, "the result of this expression is unused");
+	assert_eq!(warning.file.as_deref(), other.to_str());
+	assert_eq!(warning.get_range(), Some(12..17));
+	std::fs::remove_dir_all(dir).unwrap();
+}
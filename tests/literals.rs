@@ -0,0 +1,131 @@
+mod common;
+
+use common::*;
+
+#[test]
+fn integers_may_be_written_in_other_bases() {
+	let run = run_f("script f {\n\tu16 hex = $FFFF;\n\tu16 x = 0x1_F;\n\tu8 binary = 0b1010;\n\tu8 percent = %1_010;\n\tu8 decimal = 1_0;\n}\n");
+	assert_eq!(run.word("hex"), 0xFFFF);
+	assert_eq!(run.word("x"), 0x1F);
+	assert_eq!(run.byte("binary"), 10);
+	assert_eq!(run.byte("percent"), 10);
+	assert_eq!(run.byte("decimal"), 10);
+}
+
+#[test]
+fn negated_hexadecimal_is_negative() {
+	let run = run_f("script f {\n\ti8 a = -$80;\n}\n");
+	assert_eq!(run.byte("a") as i8, -128);
+}
+
+#[test]
+fn percent_is_still_modulo() {
+	let asm = compile(&with_std("script f {\n\tu16 x = 25;\n\tx = x%10;\n}\n"));
+	assert!(function(&asm, "f").iter().any(|line| line.starts_with("db script@mod_u16")));
+}
+
+#[test]
+fn percent_is_binary_only_where_a_value_is_expected() {
+	compile(&with_std("static_assert(%1010 == 10);\nstatic_assert(25%10 == 5);\nstatic_assert(25 % %11 == 1);\n"));
+	assert_eq!(compile_error(&with_std("static_assert(%12 == 12);\n")), "% must be directly followed by binary digits, as in %1010");
+}
+
+#[test]
+fn alias_arguments_may_be_written_with_or_without_a_space() {
+	let source = with_std("env other {\n\tuse std;\n\tdef foo(u8, u8);\n\talias bar(u8) = foo($ 1, 2);\n\talias baz(u8) = foo($1, $1F);\n}\nother f {\n\tbar(3);\n\tbaz(4);\n}\n");
+	let body = function(&compile(&source), "f");
+	assert_eq!(&body[..2], ["db other@put_u8, 0, 3", "db other@put_u8, 0, 2"]);
+	assert_eq!(&body[3..5], ["db other@put_u8, 0, 4", "db other@put_u8, 0, 31"]);
+}
+
+#[test]
+fn character_literals_are_ascii_codes() {
+	let run = run_f("script f {\n\tu8 a = 'A';\n\tu8 b = '\\n';\n\tu8 c = '\\x7F';\n}\n");
+	assert_eq!(run.byte("a"), b'A');
+	assert_eq!(run.byte("b"), b'\n');
+	assert_eq!(run.byte("c"), 0x7F);
+}
+
+#[test]
+fn character_literals_are_exactly_one_character() {
+	compile(&with_std("static_assert('\\t' == 9);\nstatic_assert('\\\\' == 92);\nstatic_assert('\\'' == 39);\n"));
+	let one_character = "A character literal must contain exactly one character";
+	assert_eq!(compile_error(&with_std("static_assert('' == 0);\n")), one_character);
+	assert_eq!(compile_error(&with_std("static_assert('AB' == 0);\n")), one_character);
+	assert_eq!(compile_error(&with_std("static_assert('\\xFFF' == 0);\n")), one_character);
+	assert_eq!(compile_error(&with_std("static_assert('\\q' == 0);\n")), "Unknown escape sequence (expected \\n, \\t, \\\\, \\' or \\xNN)");
+	assert_eq!(compile_error(&with_std("static_assert('é' == 0);\n")), "A character literal must be ASCII");
+}
+
+#[test]
+fn constant_alias_arguments_must_fit_in_a_byte() {
+	let alias = |argument: &str| compile_error(&with_std(&format!(
+		"env other {{\n\tuse std;\n\tdef foo(u8);\n\talias bar() = foo(const {argument});\n}}\nother f {{\n\tbar();\n}}\n"
+	)));
+	assert_eq!(alias("300"), "300 does not fit in 8 bits");
+	assert_eq!(alias("2 * 200"), "400 does not fit in 8 bits");
+}
+
+#[test]
+fn scripts_with_only_small_literals_use_bytes() {
+	let asm = compile(&with_std("script f {\n\tu8 a = 1;\n\tu8 b = a + 2;\n\tu16 c = 3;\n}\n"));
+	let body = function(&asm, "f");
+	assert!(body.iter().all(|line| !line.starts_with("db script@put_u16")));
+	// Declaring a word still makes one.
+	assert!(body.contains(&String::from("db script@put_u8, 3 + 1, 0")));
+}
+
+#[test]
+fn a_literal_above_a_byte_makes_the_script_use_words() {
+	let source = "script f {\n\tu8 a = 1;\n\tu16 b = a + 300;\n}\n";
+	let body = function(&compile(&with_std(source)), "f");
+	assert!(body.contains(&String::from("db script@put_u16, 0, 1 & $FF, 1 >> 8")));
+	assert!(body.contains(&String::from("db script@put_u16, 0, 300 & $FF, 300 >> 8")));
+	assert_eq!(run_f(source).word("b"), 301);
+}
+
+#[test]
+fn constant_expressions_above_a_byte_make_the_script_use_words() {
+	let body = function(&compile(&with_std("script f {\n\tu8 a = 1;\n\tu16 b = a + 200 * 2;\n}\n")), "f");
+	assert!(body.contains(&String::from("db script@put_u16, 0, 1 & $FF, 1 >> 8")));
+}
+
+#[test]
+fn string_escapes_are_decoded() {
+	let asm = compile(&with_std("script f {\n\tu16 s = \"a\\n\\t\\\\\\\"b\\0\\x7Fc\";\n\tstatic_assert(strlen(\"a\\0\\x41\") == 3);\n}\n"));
+	let body = function(&asm, "f");
+	let string = body.iter().position(|line| line == ".__string0").unwrap();
+	// Characters are still written for RGBDS, so that they go through the charmap; bytes are not.
+	assert_eq!(body[string + 1], "db \"a\\n\\t\\\\\\\"b\", $00, $7F, \"c\", 0");
+}
+
+#[test]
+fn strlen_is_a_constant_count_of_characters() {
+	let source = "script f {\n\tu8 size = strlen(\"hello\") + 1;\n\tstatic_assert(strlen(\"\") == 0);\n}\n";
+	assert_eq!(run_f(source).byte("size"), 6);
+	// The string itself is never written.
+	assert!(!compile(&with_std(source)).contains("__string"));
+}
+
+#[test]
+fn unknown_string_escapes_are_rejected() {
+	let msg = compile_error(&with_std("script f {\n\tu16 s = \"\\q\";\n}\n"));
+	assert_eq!(msg, "Unknown escape sequence \\q (expected \\n, \\t, \\0, \\\\, \\\" or \\xNN)");
+}
+
+#[test]
+fn unterminated_hexadecimal_escapes_are_rejected() {
+	let msg = compile_error(&with_std("script f {\n\tu16 s = \"\\x4\";\n}\n"));
+	assert_eq!(msg, "\\x must be followed by two hexadecimal digits");
+	let msg = compile_error(&with_std("script f {\n\tu16 s = strlen(\"\\xG0\");\n}\n"));
+	assert_eq!(msg, "\\x must be followed by two hexadecimal digits");
+}
+
+#[test]
+fn identical_strings_are_written_once() {
+	let source = "env vm {\n\tuse std;\n\tdef print(u16);\n\tpool = 16;\n}\nvm f {\n\tprint(\"hi\");\n\tprint(\"hi\");\n\tprint(\"ho\");\n}\n";
+	let body = function(&compile(&with_std(source)), "f");
+	assert_eq!(body.iter().filter(|line| line.starts_with("db \"")).collect::<Vec<_>>(), ["db \"hi\", 0", "db \"ho\", 0"]);
+	// Both prints of "hi" put the address of the same label.
+	assert_eq!(body.iter().filter(|line| line.contains("LOW(.__string0)")).count(), 2);
+}
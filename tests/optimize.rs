@@ -0,0 +1,45 @@
+mod common;
+
+use common::*;
+use evscript::compiler::CompilerOptions;
+
+fn optimized(source: &str) -> Vec<String> {
+	let mut options = CompilerOptions::new();
+	options.optimize = true;
+	let asm = compile_with(&with_std(source), options).unwrap_or_else(|msg| panic!("Compilation failed: {msg}"));
+	function(&asm, "f")
+}
+
+#[test]
+fn always_true_conditions_keep_only_their_body() {
+	assert_eq!(optimized("script f {\n\tif 1 {\n\t\tu8 a = 1;\n\t} else {\n\t\tu8 b = 2;\n\t}\n}\n"), [
+		"db script@put_u8, 0, 1",
+		"db 0",
+	]);
+	assert_eq!(optimized("script f {\n\tif -1 {\n\t\tyld();\n\t}\n}\n"), ["db script@yld", "db 0"]);
+}
+
+#[test]
+fn always_false_conditions_keep_only_what_runs() {
+	assert_eq!(optimized("script f {\n\tif 0 {\n\t\tu8 a = 1;\n\t} else {\n\t\tu8 b = 2;\n\t}\n}\n"), [
+		"db script@put_u8, 0, 2",
+		"db 0",
+	]);
+	assert_eq!(optimized("script f {\n\twhile 0 {\n\t\tyld();\n\t}\n}\n"), ["db 0"]);
+	// The body of a `do` loop runs once, without the loop around it.
+	assert_eq!(optimized("script f {\n\tdo {\n\t\tyld();\n\t} while 0;\n}\n"), ["db script@yld", "db 0"]);
+}
+
+#[test]
+fn other_conditions_are_compiled() {
+	let body = optimized("script f {\n\tu8 a = 1;\n\tif a {\n\t\tyld();\n\t}\n\twhile a {\n\t\ta -= 1;\n\t}\n}\n");
+	assert!(body.iter().any(|line| line.starts_with("db script@jmp_if_false")));
+	assert!(body.iter().any(|line| line.starts_with("db script@jmp_if_true")));
+	assert!(body.contains(&String::from("db script@yld")));
+}
+
+#[test]
+fn without_optimize_constant_conditions_are_compiled() {
+	let asm = compile(&with_std("script f {\n\tif 0 {\n\t\tyld();\n\t}\n}\n"));
+	assert!(function(&asm, "f").iter().any(|line| line.starts_with("db script@jmp_if_false")));
+}
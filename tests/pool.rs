@@ -0,0 +1,44 @@
+mod common;
+
+use common::*;
+
+fn with_reservation(reservation: &str, source: &str) -> String {
+	format!(
+		"include \"{}/scripts/std.evs\";\nenv script {{\n\tuse std;\n\tpool = 256;\n\t{reservation}\n}}\n{source}",
+		env!("CARGO_MANIFEST_DIR"),
+	)
+}
+
+#[test]
+fn reservations_may_end_at_the_end_of_the_pool() {
+	let asm = compile(&with_reservation("reserve 6 at 250;", "script f {\n\tu8 a = 1;\n}\n"));
+	assert_eq!(function(&asm, "f"), ["db script@put_u8, 0, 1", "db 0"]);
+}
+
+#[test]
+fn reservations_past_the_end_of_the_pool_are_rejected() {
+	let msg = compile_error(&with_reservation("reserve 7 at 250;", "script f {\n}\n"));
+	assert_eq!(msg, "Reserved region 250-256 does not fit in the pool");
+}
+
+#[test]
+fn the_whole_pool_cannot_be_reserved() {
+	let msg = compile_error(&with_reservation("reserve 256 at 0;", "script f {\n}\n"));
+	assert_eq!(msg, "Cannot reserve 256 bytes");
+}
+
+#[test]
+fn variables_are_not_placed_in_reserved_bytes() {
+	let asm = compile(&with_reservation("reserve 3 at 1;", "script f {\n\tu8 a = 1;\n\tu16 b = 2;\n\tu8 c = 3;\n}\n"));
+	let body = function(&asm, "f");
+	assert_eq!(body[0], "db script@put_u8, 0, 1");
+	assert!(body.iter().all(|line| !line.contains(", 1, ") && !line.contains(", 2, ") && !line.contains(", 3, ")));
+}
+
+#[test]
+fn variables_may_not_overlap_the_end_of_a_reservation() {
+	let asm = compile(&with_reservation("reserve u8 flag at 1;", "script f {\n\tu8 a = flag;\n\tu16 b = 2;\n}\n"));
+	let body = function(&asm, "f");
+	assert!(body.contains(&String::from("db script@mov_u8, 0, 1")));
+	assert!(body.iter().any(|line| line.starts_with("db script@put_u8, 2,")));
+}
@@ -0,0 +1,31 @@
+mod common;
+
+use common::*;
+use evscript::compiler::CompilerOptions;
+use evscript::Compiler;
+
+#[test]
+fn unused_definitions_are_reported_apart_from_the_assembly() {
+	let mut options = CompilerOptions::new();
+	options.report_unused = true;
+	let (asm, compilation) = Compiler::new("test.evs")
+		.source(&with_std("script f {\n\tyld();\n}\n"))
+		.options(options)
+		.compile_to_string();
+
+	let unused = compilation.result.unwrap_or_else(|_| panic!("Compilation failed")).unused_definitions;
+	assert!(unused.contains(&(String::from("std"), String::from("add_u8"))));
+	assert!(!unused.contains(&(String::from("std"), String::from("yld"))));
+	assert!(!asm.unwrap().contains("Unused"));
+}
+
+#[test]
+fn definitions_used_through_an_alias_are_not_reported() {
+	let mut options = CompilerOptions::new();
+	options.report_unused = true;
+	let source = "env vm {\n\tdef ret();\n\tdef wait();\n\tdef stop();\n\talias halt() = stop();\n\tpool = 16;\n}\nvm f {\n\thalt();\n}\n";
+	let (_, compilation) = Compiler::new("test.evs").source(source).options(options).compile_to_string();
+
+	let unused = compilation.result.unwrap_or_else(|_| panic!("Compilation failed")).unused_definitions;
+	assert_eq!(unused, [(String::from("vm"), String::from("wait"))]);
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use common::*;
+
+#[test]
+fn signed_bytes_are_sign_extended() {
+	let run = run_f("script f {\n\ti8 a = -3;\n\ti16 b = a;\n\tu8 c = 253;\n\ti16 d = c;\n}\n");
+	assert_eq!(run.byte("a"), 0xFD);
+	assert_eq!(run.word("b") as i16, -3);
+	assert_eq!(run.word("d"), 253);
+}
+
+#[test]
+fn signed_words_are_truncated() {
+	let run = run_f("script f {\n\ti16 a = -300;\n\ti8 b = a;\n}\n");
+	assert_eq!(run.word("a") as i16, -300);
+	assert_eq!(run.byte("b"), (-300i16) as u8);
+}
+
+#[test]
+fn negating_a_variable_is_signed() {
+	let run = run_f("script f {\n\tu8 a = 3;\n\ti16 b = -a;\n\ti8 c = 5;\n\ti16 d = -c;\n}\n");
+	assert_eq!(run.word("b") as i16, -3);
+	assert_eq!(run.word("d") as i16, -5);
+}
+
+#[test]
+fn mixed_signedness_selects_signed_opcodes() {
+	let asm = compile(&with_std("script f {\n\ti16 a = -3;\n\tu16 b = 2;\n\ti16 c = a / b;\n}\n"));
+	assert!(function(&asm, "f").iter().any(|line| line.contains("div_i16")));
+}
+
+#[test]
+fn negating_a_word_puts_a_whole_word() {
+	let run = run_f("script f {\n\tu16 a = 300;\n\ti16 b = -a;\n\ti16 c = -b;\n}\n");
+	assert_eq!(run.word("b") as i16, -300);
+	assert_eq!(run.word("c"), 300);
+}
+
+#[test]
+fn not_flips_every_bit() {
+	let run = run_f("script f {\n\tu8 a = 5;\n\tu8 b = !a;\n\tu16 c = 300;\n\tu16 d = !c;\n}\n");
+	assert_eq!(run.byte("b"), !5u8);
+	assert_eq!(run.word("d"), !300u16);
+}
+
+#[test]
+fn signed_right_shifts_are_arithmetic() {
+	let source = "script f {\n\ti8 a = -8 >> 1;\n\ti8 b = -8;\n\ti8 c = b >> 1;\n\tu8 d = 248;\n\tu8 e = d >> 1;\n}\n";
+	let asm = compile(&with_std(source));
+	let body = function(&asm, "f");
+	// Only `b >> 1` is shifted at runtime; `-8 >> 1` is folded.
+	assert_eq!(body.iter().filter(|line| line.contains("shr_i8")).count(), 1);
+	assert!(body.iter().any(|line| line.contains("shr_u8")));
+	let run = run_f(source);
+	assert_eq!(run.byte("a") as i8, -4);
+	assert_eq!(run.byte("c") as i8, -4);
+	assert_eq!(run.byte("e"), 124);
+}
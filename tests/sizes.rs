@@ -0,0 +1,48 @@
+mod common;
+
+use common::*;
+use evscript::compiler::{CompilerOptions, CompilerSummary};
+use evscript::Compiler;
+
+const SHAKE: &str = "env fx {\n\tuse script;\n\tmacro shake(u8) = do_shake;\n\tpool = 16;\n}\n";
+
+fn limited(limit: usize) -> CompilerOptions {
+	let mut options = CompilerOptions::new();
+	options.max_function_size = Some(limit);
+	options
+}
+
+fn summary(source: &str) -> CompilerSummary {
+	let (_, compilation) = Compiler::new("test.evs").source(&with_std(source)).compile_to_string();
+	compilation.result.unwrap_or_else(|_| panic!("Compilation failed"))
+}
+
+#[test]
+fn function_sizes_include_strings_and_data() {
+	let summary = summary("script f {\n\tu16 s = \"hi\";\n\tdata16 [1, 2];\n}\n");
+	// Two 3-byte puts, the terminator, "hi" with its terminator, and two words.
+	assert_eq!(summary.function_bytes, 3 + 3 + 1 + 3 + 4);
+	assert_eq!(summary.macro_invocations, 0);
+}
+
+#[test]
+fn macro_invocations_are_counted_apart_from_bytes() {
+	let summary = summary(&format!("{SHAKE}fx f {{\n\tshake(1);\n}}\n"));
+	// The argument's put and the terminator.
+	assert_eq!(summary.function_bytes, 3 + 1);
+	assert_eq!(summary.macro_invocations, 1);
+}
+
+#[test]
+fn functions_over_the_limit_are_rejected() {
+	let msg = compile_with(&with_std("script f {\n\tu8 a = 1;\n\tu8 b = 2;\n}\n"), limited(6)).unwrap_err();
+	assert_eq!(msg, "f is 7 bytes long, more than the limit of 6");
+	assert!(compile_with(&with_std("script f {\n\tu8 a = 1;\n\tu8 b = 2;\n}\n"), limited(7)).is_ok());
+}
+
+#[test]
+fn functions_with_macros_are_checked_by_the_assembler() {
+	let asm = compile_with(&with_std(&format!("{SHAKE}fx f {{\n\tshake(1);\n}}\nscript g {{\n}}\n")), limited(8)).unwrap();
+	assert!(asm.contains("\tassert @ - f <= 8, \"f is more than the limit of 8 bytes long\""));
+	assert!(!asm.contains("assert @ - g"));
+}
@@ -0,0 +1,116 @@
+mod common;
+
+use common::*;
+use evscript::Compiler;
+
+const POINT: &str = "struct point {\n\tx: u8,\n\ty: u8,\n}\n";
+
+#[test]
+fn structs_compare_every_member() {
+	let run = run_f(&format!(
+		"{POINT}script f {{\n\
+		\tpoint a;\n\tpoint b;\n\
+		\ta.x = 1;\n\ta.y = 2;\n\tb.x = 1;\n\tb.y = 3;\n\
+		\tu8 equal = a == b;\n\tu8 differ = a != b;\n\
+		\tb.y = 2;\n\
+		\tu8 same = a == b;\n\tu8 same_differ = a != b;\n\
+		\tb.x = 4;\n\
+		\tu8 first_differ = a != b;\n\
+		}}\n"
+	));
+	assert_eq!(run.byte("equal"), 0);
+	assert_eq!(run.byte("differ"), 1);
+	assert_eq!(run.byte("same"), 1);
+	assert_eq!(run.byte("same_differ"), 0);
+	assert_eq!(run.byte("first_differ"), 1);
+}
+
+#[test]
+fn empty_structs_are_rejected() {
+	assert_eq!(compile_error(&with_std("struct empty {}\nscript f {\n}\n")), "empty has no members");
+}
+
+const WIDE: &str = "struct wide {\n\tx: u16,\n\ty: i16,\n\tz: u8,\n}\n";
+
+#[test]
+fn initializers_set_every_member() {
+	let run = run_f(&format!("{WIDE}script f {{\n\twide w = {{1, -2, 3}};\n\tu16 x = w.x;\n\ti16 y = w.y;\n\tu8 z = w.z;\n}}\n"));
+	assert_eq!(run.word("x"), 1);
+	assert_eq!(run.word("y") as i16, -2);
+	assert_eq!(run.byte("z"), 3);
+}
+
+#[test]
+fn missing_initializers_are_zero() {
+	let run = run_f(&format!("{WIDE}script f {{\n\twide w = {{300}};\n\tu16 x = w.x;\n\ti16 y = w.y;\n\tu8 z = w.z;\n}}\n"));
+	assert_eq!(run.word("x"), 300);
+	assert_eq!(run.word("y"), 0);
+	assert_eq!(run.byte("z"), 0);
+}
+
+#[test]
+fn too_many_initializers_are_rejected() {
+	let msg = compile_error(&with_std(&format!("{POINT}script f {{\n\tpoint p = {{1, 2, 3}};\n}}\n")));
+	assert_eq!(msg, "Too many initializers: point has 2 members, but 3 values were given");
+}
+
+#[test]
+fn static_assertions_check_sizes_at_compile_time() {
+	let passing = compile(&with_std(&format!("{POINT}script f {{\n\tstatic_assert(sizeof(point) == 2);\n}}\n")));
+	assert_eq!(function(&passing, "f"), ["db 0"]);
+
+	let source = with_std(&format!("{POINT}static_assert(sizeof(point) == 3, \"point grew!\");\n"));
+	let (_, compilation) = Compiler::new("test.evs").source(&source).compile_to_string();
+	let errors = compilation.result.err().unwrap();
+	assert_eq!(errors[0].msg, "point grew!");
+	assert!(source[errors[0].start.unwrap()..errors[0].end.unwrap()].starts_with("static_assert("));
+
+	let msg = compile_error(&with_std("script f {\n\tu8 a = 1;\n\tstatic_assert(a == 1);\n}\n"));
+	assert_eq!(msg, "Unexpected variable, expression must be constant");
+}
+
+#[test]
+fn redefining_a_struct_points_at_both_definitions() {
+	let source = format!("{POINT}struct point {{\n\tx: u16,\n}}\n");
+	let (_, compilation) = Compiler::new("test.evs").source(&source).compile_to_string();
+	let errors = compilation.result.err().unwrap();
+	assert_eq!(errors[0].msg, "Type point is already defined");
+	assert_eq!(errors[0].start.unwrap()..errors[0].end.unwrap(), POINT.len()..POINT.len() + "struct point".len());
+	assert_eq!(errors[0].previous, Some(0.."struct point".len()));
+}
+
+#[test]
+fn nested_struct_sizes_add_up_and_recursion_is_rejected() {
+	let source = format!("{POINT}typedef coord = u16;\nstruct line {{\n\tfrom: point,\n\tto: point,\n\tlength: coord,\n}}\nscript f {{\n\tline a;\n\tu8 b = 1;\n\tstatic_assert(sizeof(line) == 6);\n}}\n");
+	// `b` is placed after the 6 bytes of `a`.
+	assert_eq!(run_f(&source).pool[6], 1);
+
+	let msg = compile_error(&with_std("struct node {\n\tvalue: u8,\n\tnext: node,\n}\n"));
+	assert_eq!(msg, "node cannot contain itself; its size would be infinite");
+}
+
+#[test]
+fn chained_members_are_at_their_cumulative_offset() {
+	let source = format!("{POINT}struct line {{\n\tfrom: point,\n\tto: point,\n}}\nscript f {{\n\tline a;\n\ta.to.y = 7;\n\tu8 b = a.to.y;\n}}\n");
+	let run = run_f(&source);
+	assert_eq!(run.pool[3], 7);
+	assert_eq!(run.byte("b"), 7);
+
+	let source = with_std(&format!("{POINT}script f {{\n\tpoint a;\n\ta.z = 1;\n}}\n"));
+	let (_, compilation) = Compiler::new("test.evs").source(&source).compile_to_string();
+	let error = compilation.result.err().unwrap().remove(0);
+	assert_eq!(error.msg, "z is not a member of a");
+	assert_eq!(&source[error.start.unwrap()..error.end.unwrap()], "a.z");
+	assert_eq!(compile_error(&with_std(&format!("{POINT}script f {{\n\tpoint a;\n\tu8 b = a.z;\n}}\n"))), "z is not a member of a");
+	assert_eq!(compile_error(&with_std(&format!("{POINT}script f {{\n\tpoint a;\n\tu8 b = a.x.y;\n}}\n"))), "x is a u8 and has no members");
+}
+
+#[test]
+fn typedefs_are_resolved_to_their_type() {
+	let run = run_f("typedef word = u16;\ntypedef count = word;\nscript f {\n\tcount a = 300;\n\tu8 b = 1;\n}\n");
+	assert_eq!(run.word("a"), 300);
+	// `b` is placed after both bytes of `a`.
+	assert_eq!(run.pool[2], 1);
+
+	assert_eq!(compile_error(&with_std("typedef word = word;\n")), "word cannot be defined as itself");
+}
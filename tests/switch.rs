@@ -0,0 +1,23 @@
+mod common;
+
+use common::*;
+
+const SWITCH: &str = "\tu8 a = 0;\n\tu8 b = 0;\n\tswitch value {\n\t\tcase 1 {\n\t\t\ta = 1;\n\t\t}\n\t\tcase 2 {\n\t\t\ta = 2;\n\t\t\tfallthrough;\n\t\t}\n\t\tcase 3 {\n\t\t\tb = 3;\n\t\t}\n\t}\n";
+
+fn run_switch(value: u8) -> Run {
+	run_f(&format!("script f {{\n\tu8 value = {value};\n{SWITCH}}}\n"))
+}
+
+#[test]
+fn cases_break_at_their_end() {
+	let run = run_switch(1);
+	assert_eq!(run.byte("a"), 1);
+	assert_eq!(run.byte("b"), 0);
+}
+
+#[test]
+fn fallthrough_continues_into_the_next_case() {
+	let run = run_switch(2);
+	assert_eq!(run.byte("a"), 2);
+	assert_eq!(run.byte("b"), 3);
+}
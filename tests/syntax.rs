@@ -0,0 +1,46 @@
+use evscript::unclosed_brace;
+use evscript::Compiler;
+
+#[test]
+fn unclosed_brace_is_the_innermost_one() {
+	let input = "script f {\n\tif 1 {\n\t}\n\twhile 1 {\n";
+	assert_eq!(unclosed_brace(input), input.rfind('{'));
+	assert_eq!(unclosed_brace("script f {\n}\n"), None);
+}
+
+#[test]
+fn unclosed_brace_ignores_literals_and_comments() {
+	let input = "script f {\n\tprint(\"\\\" }\");\n\tu8 c = '}';\n\t/* /* } */ } */\n\t// }\n\t#asm } #end\n";
+	assert_eq!(unclosed_brace(input), Some(9));
+}
+
+#[test]
+fn keywords_used_as_names_are_reported_as_reserved() {
+	let (_, compilation) = Compiler::new("test.evs").source("script f {\n\tu8 data = 1;\n}\n").compile_to_string();
+	let errors = compilation.result.err().unwrap();
+	assert_eq!(errors[0].msg, "data is a reserved word, and cannot be used as a name");
+}
+
+#[test]
+fn every_invalid_statement_is_reported() {
+	let input = "script f {\n\tu8 a = ;\n\tu8 b = 1;\n\tb = * 2;\n}\n";
+	let (_, compilation) = Compiler::new("test.evs").source(input).compile_to_string();
+	// Each error is within its own statement.
+	let lines = compilation.result.err().unwrap().iter()
+		.map(|err| input[..err.start.unwrap()].lines().count())
+		.collect::<Vec<_>>();
+	assert_eq!(lines, [2, 4]);
+}
+
+#[test]
+fn comments_are_skipped_without_moving_errors() {
+	let input = "// line comment\n/* block /* nested */ comment */\nscript f {\n\t/* before */ u8 a = ;\n}\n";
+	let (_, compilation) = Compiler::new("test.evs").source(input).compile_to_string();
+	let errors = compilation.result.err().unwrap();
+	assert_eq!(errors.len(), 1);
+	// The error is at the `;`, not shifted by the comments before it.
+	assert_eq!(errors[0].start, input.find(" ;").map(|i| i + 1));
+
+	let (_, compilation) = Compiler::new("test.evs").source("env vm {\n\tpool = 16; // size\n}\nvm f { /* /* */ */ }\n").compile_to_string();
+	assert!(compilation.result.is_ok());
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use common::*;
+use evscript::compiler::CompilerOptions;
+
+const SOURCE: &str = "script f {\n\tu8 a = 1;\n\tu16 x = a + 300;\n\tif x {\n\t\tyld();\n\t}\n}\n";
+
+fn compile_for(target: &str) -> String {
+	let options = CompilerOptions::target(target).unwrap();
+	compile_with(&with_std(SOURCE), options).unwrap_or_else(|msg| panic!("Compilation failed: {msg}"))
+}
+
+#[test]
+fn gb_default_is_the_default() {
+	assert_eq!(function(&compile_for("gb-default"), "f"), function(&compile(&with_std(SOURCE)), "f"));
+}
+
+#[test]
+fn custom_be_changes_words_without_other_flags() {
+	let asm = compile_for("custom-be");
+	let body = function(&asm, "f");
+	// Integers are u16, so even the small constant is put as a word, high byte first.
+	assert!(body.contains(&String::from("db script@put_u16, 0, 1 >> 8, 1 & $FF")));
+	assert!(body.contains(&String::from("db script@put_u16, 0, 300 >> 8, 300 & $FF")));
+	// Jumps are relative to their opcode.
+	assert!(body.contains(&String::from("db script@jmp_if_false, 5, HIGH(.__else0 - @), LOW(.__else0 - @)")));
+	assert_eq!(body.last().unwrap(), "db script@ret");
+}
+
+#[test]
+fn flags_override_the_profile() {
+	let options = CompilerOptions {
+		big_endian: false,
+		relative_jumps: false,
+		terminator: None,
+		..CompilerOptions::target("custom-be").unwrap()
+	};
+	let asm = compile_with(&with_std(SOURCE), options).unwrap();
+	let body = function(&asm, "f");
+	assert!(body.contains(&String::from("db script@jmp_if_false, 5, LOW(.__else0), HIGH(.__else0)")));
+	assert_eq!(body.last().unwrap(), "db 0");
+}
+
+#[test]
+fn unknown_targets_are_rejected() {
+	assert_eq!(
+		CompilerOptions::target("z80").err().unwrap(),
+		"Unknown target z80; expected one of: gb-default custom-be",
+	);
+}
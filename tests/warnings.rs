@@ -0,0 +1,70 @@
+mod common;
+
+use common::*;
+use evscript::compiler::CompilerOptions;
+use evscript::Compiler;
+
+#[test]
+fn stores_overwritten_before_they_are_read_are_reported() {
+	let warnings = warnings(&with_std("script f {\n\tu8 x;\n\tx = 1;\n\tx = 2;\n\tu8 y = x;\n}\n"));
+	assert_eq!(warnings, ["the value stored in x is overwritten before it is read"]);
+}
+
+#[test]
+fn initializers_may_be_overwritten() {
+	let source = "env vm {\n\tuse std;\n\tpool = 16;\n\tdef rand(return u8);\n}\nvm f {\n\tu8 x = 0;\n\tx = rand();\n\tu8 y = x;\n}\n";
+	let warnings = warnings(&with_std(source));
+	assert!(warnings.is_empty(), "{warnings:?}");
+}
+
+const POINTERS: &str = "script f {\n\tu16 address = 0xC000;\n\tu8 ptr bytes = (u8 ptr) address;\n\tu16 ptr words = bytes;\n\tu16 ptr cast = (u16 ptr) bytes;\n}\n";
+
+#[test]
+fn assigning_between_incompatible_pointers_warns() {
+	let warnings = warnings(&with_std(POINTERS));
+	// Only the assignment without a cast is reported.
+	assert_eq!(warnings, ["Assigning between incompatible pointer types; use a pointer cast if this is intended"]);
+}
+
+#[test]
+fn assigning_between_incompatible_pointers_is_an_error_with_strict_types() {
+	let options = CompilerOptions { strict_types: true, ..CompilerOptions::new() };
+	assert_eq!(
+		compile_with(&with_std(POINTERS), options),
+		Err(String::from("Assigning between incompatible pointer types; use a pointer cast if this is intended")),
+	);
+}
+
+#[test]
+fn yields_with_too_many_live_variables_are_reported() {
+	let source = with_std("script f {\n\tu8 a = 1;\n\tyield;\n\tu8 b = 2;\n\tu8 c = a + b;\n\tyield;\n\tu8 d = c;\n}\n");
+	let options = CompilerOptions { yield_depth_limit: Some(2), ..CompilerOptions::new() };
+	let (_, compilation) = Compiler::new("test.evs").source(&source).options(options).compile_to_string();
+	compilation.result.unwrap_or_else(|errors| panic!("Compilation failed: {}", errors[0].msg));
+	let warnings = compilation.warnings.into_iter().map(|warning| warning.msg).collect::<Vec<_>>();
+	// Only the second yield has more than `a` to keep.
+	assert_eq!(warnings, ["yielding with 3 live variables, more than the limit of 2"]);
+}
+
+#[test]
+fn definitions_from_two_used_environments_are_reported() {
+	let source = "env a {\n\tdef wait();\n}\nenv b {\n\tdef wait();\n}\nenv vm {\n\tuse a;\n\tuse b;\n\tpool = 16;\n}\nvm f {}\n";
+	let (_, compilation) = Compiler::new("test.evs").source(source).compile_to_string();
+	compilation.result.unwrap_or_else(|errors| panic!("Compilation failed: {}", errors[0].msg));
+	assert_eq!(compilation.warnings.len(), 1);
+	let warning = &compilation.warnings[0];
+	assert_eq!(warning.msg, "wait is defined by both a and b; the definition from b is used");
+	assert_eq!(&source[warning.start.unwrap()..warning.end.unwrap()], "use b");
+}
+
+#[test]
+fn expression_statements_whose_result_is_unused_are_reported() {
+	let source = with_std("script f {\n\tu8 a = 1;\n\tu8 b = 2;\n\ta + b;\n\tyld();\n}\n");
+	let (_, compilation) = Compiler::new("test.evs").source(&source).compile_to_string();
+	compilation.result.unwrap_or_else(|errors| panic!("Compilation failed: {}", errors[0].msg));
+	// The call is made for its side effects, so only `a + b` is reported.
+	assert_eq!(compilation.warnings.len(), 1);
+	let warning = &compilation.warnings[0];
+	assert_eq!(warning.msg, "the result of this expression is unused");
+	assert_eq!(&source[warning.start.unwrap()..warning.end.unwrap()], "a + b");
+}